@@ -1,5 +1,6 @@
+use crate::message::{HASH_SIZE, SIGNATURE_SIZE};
 use crate::qc::Qc;
-use crate::transaction::{Shard, Transaction};
+use crate::transaction::{AbortReason, Shard, Transaction};
 use itertools::Itertools;
 use std::sync::Arc;
 
@@ -14,9 +15,17 @@ pub struct Block {
     pub prepare_txs: Vec<Arc<Transaction>>,
     pub precommit_txs: Vec<Arc<Transaction>>,
     pub commit_txs: Vec<Arc<Transaction>>,
+    /// Transactions this shard votes to abort instead of preparing them. They go through
+    /// precommit and commit like any other transaction, but commit as aborted in every shard
+    pub abort_txs: Vec<(Arc<Transaction>, AbortReason)>,
+    /// The transactions in `precommit_txs` and `commit_txs` that this block precommits or commits
+    /// as aborted. Every VN follows the leader here, so a shard finishes a transaction one way
+    /// however its VNs heard about the abort
+    pub aborted: Vec<(u32, AbortReason)>,
 }
 
 impl Block {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: u32,
         parent_id: u32,
@@ -27,6 +36,8 @@ impl Block {
         prepare_txs: Vec<Arc<Transaction>>,
         precommit_txs: Vec<Arc<Transaction>>,
         commit_txs: Vec<Arc<Transaction>>,
+        abort_txs: Vec<(Arc<Transaction>, AbortReason)>,
+        aborted: Vec<(u32, AbortReason)>,
     ) -> Self {
         Self {
             id,
@@ -38,6 +49,8 @@ impl Block {
             prepare_txs,
             precommit_txs,
             commit_txs,
+            abort_txs,
+            aborted,
         }
     }
 
@@ -52,9 +65,39 @@ impl Block {
             prepare_txs: vec![],
             precommit_txs: vec![],
             commit_txs: vec![],
+            abort_txs: vec![],
+            aborted: vec![],
         }
     }
 
+    /// Bytes on the wire and in storage: the header, justify QC and every transaction in it
+    pub fn size(&self) -> u64 {
+        let header = 2 * HASH_SIZE + 4 + 8 + HASH_SIZE + SIGNATURE_SIZE;
+        header
+            + self.justify.size()
+            + self.transaction_bytes()
+            + self.aborted.len() as u64 * (HASH_SIZE + 1)
+    }
+
+    /// The part of `size` taken up by transactions
+    pub fn transaction_bytes(&self) -> u64 {
+        self.prepare_txs
+            .iter()
+            .chain(&self.precommit_txs)
+            .chain(&self.commit_txs)
+            .chain(self.abort_txs.iter().map(|(tx, _)| tx))
+            .map(|tx| tx.size())
+            .sum()
+    }
+
+    /// Why this block precommits or commits the transaction as aborted, if it does
+    pub fn abort_reason(&self, tx_id: u32) -> Option<AbortReason> {
+        self.aborted
+            .iter()
+            .find(|(id, _)| *id == tx_id)
+            .map(|(_, reason)| *reason)
+    }
+
     pub fn involved_shards(&self) -> Vec<Shard> {
         let mut res = vec![];
         for tx in self.prepare_txs.iter() {
@@ -64,7 +107,11 @@ impl Block {
                 }
             }
         }
-        for tx in self.precommit_txs.iter() {
+        for tx in self
+            .precommit_txs
+            .iter()
+            .chain(self.abort_txs.iter().map(|(tx, _)| tx))
+        {
             for shard in tx.shards.iter() {
                 if !res.contains(shard) {
                     res.push(*shard);
@@ -1,42 +1,63 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use dansim::committee_size::CommitteeSizeArgs;
+use dansim::max_tps::MaxTpsArgs;
+use dansim::sweep::SweepArgs;
+use dansim::SimulationConfig;
+use std::path::PathBuf;
 
-#[derive(Parser, Debug)]
+/// Simulates HotStuff consensus across Cerberus shards
+#[derive(Parser, Debug, Clone)]
 pub struct Cli {
-    #[clap(short, long, default_value = "20")]
-    pub num_vns: usize,
-    #[clap(long, default_value = "50ms")]
-    pub min_latency: humantime::Duration,
-    #[clap(long, default_value = "50ms")]
-    pub max_latency: humantime::Duration,
-    #[clap(long, default_value = "10")]
-    pub max_block_size: usize,
-    #[clap(long, default_value = "5")]
-    pub num_shards: u32,
-
-    ///  The time before deciding a block has timed out
-    #[clap(long, default_value = "5000ms")]
-    pub delta: humantime::Duration,
-
-    #[clap(long, default_value = "40")]
-    pub num_steps: usize,
-    #[clap(long, default_value = "100ms")]
-    pub time_per_step: humantime::Duration,
-
-    #[clap(long, default_value = "10")]
-    pub print_stats_every: usize,
-
-    #[clap(long, default_value = "5")]
-    pub max_tx_per_step_per_block: usize,
-
-    #[clap(long, default_value = "500")]
-    pub num_transactions: usize,
-
-    #[clap(long, default_value = "10")]
-    pub probability_2_shards: u32,
-    #[clap(long, default_value = "5")]
-    pub probability_3_shards: u32,
-    #[clap(long, default_value = "0")]
-    pub probability_4_shards: u32,
-    #[clap(long, default_value = "0")]
-    pub probability_5_shards: u32,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// Load the scenario from this TOML or JSON file. Flags given on the command line override
+    /// the values in it
+    #[clap(long)]
+    pub scenario: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub simulation: SimulationConfig,
+
+    /// Run the scenario this many times under seeds derived from --seed and report the mean and
+    /// 95% confidence interval of the headline metrics
+    #[clap(long, default_value = "1")]
+    pub replications: usize,
+
+    /// Write every event to this file as JSON Lines
+    #[clap(long)]
+    pub trace_file: Option<PathBuf>,
+
+    /// Write the phase latencies of every transaction to this file as CSV
+    #[clap(long)]
+    pub latency_report: Option<PathBuf>,
+
+    /// Write the TPS time series to this file as CSV
+    #[clap(long)]
+    pub throughput_report: Option<PathBuf>,
+
+    /// Write the execution memory of every VN over time to this file as CSV
+    #[clap(long)]
+    pub memory_report: Option<PathBuf>,
+
+    /// Write the simulation to a Neo4j graph at this address, e.g. 127.0.0.1:7687
+    #[clap(long, env = "DANSIM_NEO4J_URI")]
+    pub neo4j_uri: Option<String>,
+    #[clap(long, env = "DANSIM_NEO4J_USER", default_value = "neo4j")]
+    pub neo4j_user: String,
+    #[clap(long, env = "DANSIM_NEO4J_PASSWORD", default_value = "password123")]
+    pub neo4j_password: String,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Run the simulation at increasing load to find the highest sustainable TPS
+    MaxTps(MaxTpsArgs),
+    /// Run every combination of a set of parameters and write the results as CSV
+    Sweep(SweepArgs),
+    /// Split the same VNs into different numbers of shards and compare the safety and performance
+    /// of each committee size
+    CommitteeSize(CommitteeSizeArgs),
+    /// Work out the chance that a committee is compromised, without running the simulation
+    CommitteeSafety,
 }
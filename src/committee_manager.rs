@@ -19,7 +19,7 @@ impl CommitteeManager {
 
     pub async fn add_validator(&self, shard: Shard, id: u32) {
         let mut inner = self.inner.write().await;
-        let committee = inner.committees.entry(shard).or_insert_with(|| Vec::new());
+        let committee = inner.committees.entry(shard).or_default();
         committee.push(id);
         committee.sort();
     }
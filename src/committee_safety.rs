@@ -0,0 +1,312 @@
+use crate::config::{CommitteeAssignment, SimulationConfig};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::{Rng, SeedableRng};
+
+/// When the Byzantine members of a committee of `n` are enough to break it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threshold {
+    /// More than f = (n-1)/3 Byzantine members, so HotStuff's safety proof no longer holds
+    FaultBound,
+    /// At least n - (n-1)/3 Byzantine members, the number of votes `on_receive_vote` needs for a
+    /// QC, so they can certify blocks on their own
+    Quorum,
+}
+
+impl Threshold {
+    pub fn is_compromised(&self, n: usize, byzantine: usize) -> bool {
+        if n == 0 {
+            return false;
+        }
+        match self {
+            Threshold::FaultBound => byzantine > (n - 1) / 3,
+            Threshold::Quorum => byzantine >= n - (n - 1) / 3,
+        }
+    }
+}
+
+/// The chance that at least one committee is compromised
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Estimate {
+    /// Exact for round robin committees. For random committees it is the union bound over shards
+    /// with every VN placed independently, which leaves out the VN each shard is given, so it is
+    /// only an approximate upper bound
+    pub analytical: f64,
+    pub exact: bool,
+    pub monte_carlo: f64,
+}
+
+impl Estimate {
+    /// The analytical value if it is exact, otherwise the Monte Carlo one
+    pub fn best(&self) -> f64 {
+        if self.exact {
+            self.analytical
+        } else {
+            self.monte_carlo
+        }
+    }
+}
+
+/// How likely a network is to have a compromised committee when a fixed number of its VNs,
+/// picked uniformly at random, are Byzantine
+#[derive(Debug, Clone)]
+pub struct CommitteeSafety {
+    pub num_vns: usize,
+    pub num_shards: u32,
+    pub assignment: CommitteeAssignment,
+    pub byzantine: usize,
+    pub trials: usize,
+    pub fault_bound: Estimate,
+    pub quorum: Estimate,
+}
+
+impl CommitteeSafety {
+    /// Works out both thresholds for the committees in `config`, with `byzantine_fraction` of
+    /// the VNs Byzantine. The Monte Carlo trials are seeded from the config's seed.
+    pub fn from_config(config: &SimulationConfig) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or_default());
+        Self::new(
+            config.num_vns,
+            config.num_shards,
+            config.committee_assignment,
+            (config.byzantine_fraction * config.num_vns as f64).floor() as usize,
+            config.safety_trials,
+            &mut rng,
+        )
+    }
+
+    pub fn new(
+        num_vns: usize,
+        num_shards: u32,
+        assignment: CommitteeAssignment,
+        byzantine: usize,
+        trials: usize,
+        rng: &mut StdRng,
+    ) -> Self {
+        let byzantine = byzantine.min(num_vns);
+        let estimate = |threshold: Threshold, rng: &mut StdRng| {
+            let (analytical, exact) =
+                analytical(num_vns, num_shards, assignment, byzantine, threshold);
+            Estimate {
+                analytical,
+                exact,
+                monte_carlo: monte_carlo(
+                    num_vns, num_shards, assignment, byzantine, threshold, trials, rng,
+                ),
+            }
+        };
+        Self {
+            num_vns,
+            num_shards,
+            assignment,
+            byzantine,
+            trials,
+            fault_bound: estimate(Threshold::FaultBound, rng),
+            quorum: estimate(Threshold::Quorum, rng),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("==== Committee safety ====");
+        println!(
+            "{} of {} VNs Byzantine, {} shards, {:?} committees",
+            self.byzantine, self.num_vns, self.num_shards, self.assignment
+        );
+        for (name, estimate) in [
+            ("over fault bound f", &self.fault_bound),
+            ("Byzantine quorum", &self.quorum),
+        ] {
+            println!(
+                "  P({}): analytical {}{:.6e} Monte Carlo ({} trials) {:.6e}",
+                name,
+                if estimate.exact { "" } else { "<= " },
+                estimate.analytical,
+                self.trials,
+                estimate.monte_carlo
+            );
+        }
+    }
+}
+
+fn ln_factorials(n: usize) -> Vec<f64> {
+    let mut res = vec![0.0; n + 1];
+    for i in 1..=n {
+        res[i] = res[i - 1] + (i as f64).ln();
+    }
+    res
+}
+
+/// The size of each committee when VN `i` joins shard `i % num_shards`
+fn round_robin_sizes(num_vns: usize, num_shards: u32) -> Vec<usize> {
+    let k = num_shards as usize;
+    (0..k)
+        .map(|s| num_vns / k + usize::from(s < num_vns % k))
+        .collect()
+}
+
+/// Returns the chance that some committee is compromised and whether it is exact
+pub fn analytical(
+    num_vns: usize,
+    num_shards: u32,
+    assignment: CommitteeAssignment,
+    byzantine: usize,
+    threshold: Threshold,
+) -> (f64, bool) {
+    if num_shards == 0 || num_vns == 0 {
+        return (0.0, true);
+    }
+    let ln_fact = ln_factorials(num_vns);
+    let ln_choose = |n: usize, r: usize| ln_fact[n] - ln_fact[r] - ln_fact[n - r];
+    match assignment {
+        CommitteeAssignment::RoundRobin => {
+            // Fill the committees one at a time, tracking the chance that every committee so far
+            // is safe for each number of Byzantine VNs still to place
+            let mut safe = vec![0.0; byzantine + 1];
+            safe[byzantine] = 1.0;
+            let mut remaining = num_vns;
+            for n in round_robin_sizes(num_vns, num_shards) {
+                let mut next = vec![0.0; byzantine + 1];
+                for (j, p) in safe.iter().enumerate() {
+                    if *p == 0.0 {
+                        continue;
+                    }
+                    let honest = remaining - j;
+                    for b in n.saturating_sub(honest)..=j.min(n) {
+                        if threshold.is_compromised(n, b) {
+                            continue;
+                        }
+                        let ln_p =
+                            ln_choose(j, b) + ln_choose(honest, n - b) - ln_choose(remaining, n);
+                        next[j - b] += p * ln_p.exp();
+                    }
+                }
+                safe = next;
+                remaining -= n;
+            }
+            ((1.0 - safe.iter().sum::<f64>()).clamp(0.0, 1.0), true)
+        }
+        CommitteeAssignment::Random if num_shards == 1 => (
+            f64::from(u8::from(threshold.is_compromised(num_vns, byzantine))),
+            true,
+        ),
+        CommitteeAssignment::Random => {
+            // The Byzantine and honest members of any one committee are independent binomials
+            let k = num_shards as f64;
+            let honest = num_vns - byzantine;
+            let ln_binomial = |n: usize, r: usize| {
+                ln_choose(n, r) + r as f64 * (1.0 / k).ln() + (n - r) as f64 * (1.0 - 1.0 / k).ln()
+            };
+            let mut one = 0.0;
+            for b in 0..=byzantine {
+                let ln_pb = ln_binomial(byzantine, b);
+                for h in 0..=honest {
+                    if threshold.is_compromised(b + h, b) {
+                        one += (ln_pb + ln_binomial(honest, h)).exp();
+                    }
+                }
+            }
+            ((one * k).min(1.0), false)
+        }
+    }
+}
+
+/// Places the Byzantine VNs at random `trials` times and returns the fraction of placements in
+/// which some committee is compromised. Random committees are drawn again for every trial.
+pub fn monte_carlo(
+    num_vns: usize,
+    num_shards: u32,
+    assignment: CommitteeAssignment,
+    byzantine: usize,
+    threshold: Threshold,
+    trials: usize,
+    rng: &mut StdRng,
+) -> f64 {
+    if trials == 0 || num_shards == 0 {
+        return 0.0;
+    }
+    let mut failures = 0;
+    let mut shard_of = vec![0; num_vns];
+    for _ in 0..trials {
+        for (i, shard) in shard_of.iter_mut().enumerate() {
+            *shard = match assignment {
+                CommitteeAssignment::RoundRobin => i as u32 % num_shards,
+                CommitteeAssignment::Random => rng.gen_range(0..num_shards),
+            } as usize;
+        }
+        if assignment == CommitteeAssignment::Random {
+            // As in the simulation, every shard gets a VN of its own
+            let seeded = sample(rng, num_vns, (num_shards as usize).min(num_vns));
+            for (shard, i) in seeded.into_iter().enumerate() {
+                shard_of[i] = shard;
+            }
+        }
+        let mut sizes = vec![0; num_shards as usize];
+        for shard in &shard_of {
+            sizes[*shard] += 1;
+        }
+        let mut faulty = vec![0; num_shards as usize];
+        for i in sample(rng, num_vns, byzantine.min(num_vns)) {
+            faulty[shard_of[i]] += 1;
+        }
+        if sizes
+            .iter()
+            .zip(&faulty)
+            .any(|(n, b)| threshold.is_compromised(*n, *b))
+        {
+            failures += 1;
+        }
+    }
+    failures as f64 / trials as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIALS: usize = 20_000;
+
+    fn both(assignment: CommitteeAssignment, threshold: Threshold) -> (f64, bool, f64) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (analytical, exact) = analytical(40, 4, assignment, 10, threshold);
+        let monte_carlo = monte_carlo(40, 4, assignment, 10, threshold, TRIALS, &mut rng);
+        (analytical, exact, monte_carlo)
+    }
+
+    #[test]
+    fn round_robin_matches_monte_carlo() {
+        for threshold in [Threshold::FaultBound, Threshold::Quorum] {
+            let (analytical, exact, monte_carlo) = both(CommitteeAssignment::RoundRobin, threshold);
+            assert!(exact);
+            assert!(
+                (analytical - monte_carlo).abs() < 0.01,
+                "{:?}: analytical {} Monte Carlo {}",
+                threshold,
+                analytical,
+                monte_carlo
+            );
+        }
+    }
+
+    #[test]
+    fn random_bounds_monte_carlo() {
+        let (analytical, exact, monte_carlo) =
+            both(CommitteeAssignment::Random, Threshold::FaultBound);
+        assert!(!exact);
+        assert!(
+            monte_carlo <= analytical + 0.01,
+            "analytical {} Monte Carlo {}",
+            analytical,
+            monte_carlo
+        );
+    }
+
+    #[test]
+    fn thresholds_follow_hotstuff() {
+        // n = 4 tolerates f = 1 and needs 3 votes for a QC
+        assert!(!Threshold::FaultBound.is_compromised(4, 1));
+        assert!(Threshold::FaultBound.is_compromised(4, 2));
+        assert!(!Threshold::Quorum.is_compromised(4, 2));
+        assert!(Threshold::Quorum.is_compromised(4, 3));
+        assert!(!Threshold::FaultBound.is_compromised(0, 0));
+    }
+}
@@ -0,0 +1,223 @@
+use crate::committee_safety::CommitteeSafety;
+use crate::config::SimulationConfig;
+use crate::replications::{derive_seeds, ConfidenceInterval};
+use crate::run_summary::{default_jobs, spawn_runs, RunSummary};
+use clap::Args;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+#[derive(Args, Debug, Clone)]
+pub struct CommitteeSizeArgs {
+    /// The numbers of shards to split the VNs into. Defaults to every power of two that leaves at
+    /// least 4 VNs per committee
+    #[clap(long, use_value_delimiter = true)]
+    pub shards: Vec<u32>,
+    /// Write one CSV row per split to this file
+    #[clap(long, default_value = "committee_size.csv")]
+    pub output: PathBuf,
+    /// How many runs to do at the same time. Defaults to the number of cores
+    #[clap(long)]
+    pub jobs: Option<usize>,
+}
+
+/// The performance and safety of one way of splitting the VNs into committees
+#[derive(Debug, Clone)]
+pub struct CommitteeSplit {
+    pub num_shards: u32,
+    pub mean_committee_size: f64,
+    pub latency_p50: ConfidenceInterval,
+    pub latency_p99: ConfidenceInterval,
+    pub committed_tps: ConfidenceInterval,
+    pub messages_per_tx: ConfidenceInterval,
+    pub safety: CommitteeSafety,
+    /// No other split is at least as safe, as quick to commit and as fast, and better in one of them
+    pub pareto_optimal: bool,
+}
+
+fn default_shards(num_vns: usize) -> Vec<u32> {
+    let mut shards = vec![];
+    let mut s = 1;
+    while num_vns / s >= 4 {
+        shards.push(s as u32);
+        s *= 2;
+    }
+    shards
+}
+
+/// Runs the scenario in `config` with the same VNs split into each number of shards, and prints
+/// the safety against performance tradeoff of each split
+pub async fn run(
+    config: &SimulationConfig,
+    args: &CommitteeSizeArgs,
+    replications: usize,
+) -> std::io::Result<Vec<CommitteeSplit>> {
+    let shards = if args.shards.is_empty() {
+        default_shards(config.num_vns)
+    } else {
+        args.shards.clone()
+    };
+    let replications = replications.max(1);
+    let jobs = args.jobs.unwrap_or_else(default_jobs);
+    let seed = config.seed.unwrap_or_default();
+    let byzantine = (config.byzantine_fraction * config.num_vns as f64).floor() as usize;
+    println!(
+        "Splitting {} VNs ({} Byzantine) into {:?} shards, {} runs at a time",
+        config.num_vns, byzantine, shards, jobs
+    );
+
+    let seeds = derive_seeds(seed, replications);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut configs = vec![];
+    let mut safeties = vec![];
+    for num_shards in &shards {
+        let safety = CommitteeSafety::new(
+            config.num_vns,
+            *num_shards,
+            config.committee_assignment,
+            byzantine,
+            config.safety_trials,
+            &mut rng,
+        );
+        for seed in &seeds {
+            let mut config = config.clone();
+            config.num_shards = *num_shards;
+            config.seed = Some(*seed);
+            config
+                .validate()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            configs.push((config, safety.clone()));
+        }
+        safeties.push(safety);
+    }
+    let mut handles = spawn_runs(configs, jobs).into_iter();
+
+    let mut splits = vec![];
+    for (num_shards, safety) in shards.into_iter().zip(safeties) {
+        let mut runs = vec![];
+        for handle in handles.by_ref().take(replications) {
+            runs.push(handle.await.expect("committee size run panicked"));
+        }
+        let metric = |f: fn(&RunSummary) -> f64| {
+            ConfidenceInterval::from_values(&runs.iter().map(f).collect::<Vec<_>>())
+        };
+        splits.push(CommitteeSplit {
+            num_shards,
+            mean_committee_size: config.num_vns as f64 / num_shards.max(1) as f64,
+            latency_p50: metric(|r| r.latency_p50 as f64),
+            latency_p99: metric(|r| r.latency_p99 as f64),
+            committed_tps: metric(|r| r.committed_tps),
+            messages_per_tx: metric(|r| r.messages as f64 / r.committed.max(1) as f64),
+            safety,
+            pareto_optimal: false,
+        });
+    }
+
+    // Lower is better for every measure
+    let measures = |s: &CommitteeSplit| {
+        [
+            s.safety.fault_bound.best(),
+            s.latency_p50.mean,
+            -s.committed_tps.mean,
+        ]
+    };
+    let dominates = |a: &CommitteeSplit, b: &CommitteeSplit| {
+        let (a, b) = (measures(a), measures(b));
+        a.iter().zip(&b).all(|(a, b)| a <= b) && a.iter().zip(&b).any(|(a, b)| a < b)
+    };
+    for i in 0..splits.len() {
+        splits[i].pareto_optimal = !splits.iter().any(|other| dominates(other, &splits[i]));
+    }
+
+    let mut out = BufWriter::new(File::create(&args.output)?);
+    writeln!(
+        out,
+        "num_shards,committee_size,latency_p50,latency_p50_ci95,latency_p99,latency_p99_ci95,committed_tps,committed_tps_ci95,messages_per_tx,messages_per_tx_ci95,p_over_fault_bound,p_byzantine_quorum,pareto_optimal"
+    )?;
+    println!("==== Committee size ====");
+    println!(
+        "  {:>6} {:>9} {:>10} {:>10} {:>10} {:>10} {:>12} {:>12}",
+        "shards", "VNs/shard", "p50 (ms)", "p99 (ms)", "tps", "msgs/tx", "P(b > f)", "P(b >= q)"
+    );
+    for split in &splits {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            split.num_shards,
+            split.mean_committee_size,
+            split.latency_p50.mean,
+            split.latency_p50.half_width,
+            split.latency_p99.mean,
+            split.latency_p99.half_width,
+            split.committed_tps.mean,
+            split.committed_tps.half_width,
+            split.messages_per_tx.mean,
+            split.messages_per_tx.half_width,
+            split.safety.fault_bound.best(),
+            split.safety.quorum.best(),
+            split.pareto_optimal
+        )?;
+        println!(
+            "  {:>6} {:>9.1} {:>10.0} {:>10.0} {:>10.2} {:>10.1} {:>12.6} {:>12.6}{}",
+            split.num_shards,
+            split.mean_committee_size,
+            split.latency_p50.mean,
+            split.latency_p99.mean,
+            split.committed_tps.mean,
+            split.messages_per_tx.mean,
+            split.safety.fault_bound.best(),
+            split.safety.quorum.best(),
+            if split.pareto_optimal { " *" } else { "" }
+        );
+    }
+    println!("* on the safety/performance tradeoff curve");
+    println!("Wrote {} splits to {}", splits.len(), args.output.display());
+    Ok(splits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn default_shards_leave_four_vns_per_committee() {
+        assert_eq!(default_shards(16), vec![1, 2, 4]);
+        assert_eq!(default_shards(31), vec![1, 2, 4]);
+        assert_eq!(default_shards(3), Vec::<u32>::new());
+    }
+
+    #[tokio::test]
+    async fn smaller_committees_are_less_safe() {
+        let config = SimulationConfig {
+            seed: Some(2),
+            num_vns: 16,
+            num_transactions: 20,
+            probability_2_shards: 0,
+            probability_3_shards: 0,
+            max_time: Some(Duration::from_secs(20).into()),
+            ..Default::default()
+        };
+        let args = CommitteeSizeArgs {
+            shards: vec![],
+            output: std::env::temp_dir().join("dansim_committee_size_test.csv"),
+            jobs: Some(1),
+        };
+        let splits = run(&config, &args, 1).await.unwrap();
+        std::fs::remove_file(&args.output).unwrap();
+
+        let shards: Vec<_> = splits.iter().map(|s| s.num_shards).collect();
+        assert_eq!(shards, vec![1, 2, 4]);
+        for pair in splits.windows(2) {
+            assert!(pair[0].mean_committee_size > pair[1].mean_committee_size);
+            assert!(pair[0].safety.fault_bound.best() <= pair[1].safety.fault_bound.best());
+            assert!(pair[0].safety.quorum.best() <= pair[1].safety.quorum.best());
+        }
+        // One committee of 16 tolerates 5 faults, more than the 3 Byzantine VNs
+        assert_eq!(splits[0].safety.fault_bound.best(), 0.0);
+        assert!(splits[2].safety.fault_bound.best() > 0.0);
+        assert!(splits.iter().any(|s| s.pareto_optimal));
+    }
+}
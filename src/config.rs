@@ -0,0 +1,357 @@
+use clap::{Args, Parser, ValueEnum};
+use serde::Deserialize;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How VNs are placed into shard committees
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommitteeAssignment {
+    /// VN `i` joins shard `i % num_shards`, so every committee is the same size
+    RoundRobin,
+    /// Every shard gets one VN picked at random and the other VNs join a uniformly random shard,
+    /// so committees can be uneven but never empty
+    Random,
+}
+
+/// The shape of the distribution that the execution time of each transaction is drawn from
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExecutionTimeDistribution {
+    /// Every transaction takes exactly the mean
+    Fixed,
+    /// Uniform between 0 and twice the mean
+    Uniform,
+    /// Exponential with the given mean, so a few transactions take much longer
+    Exponential,
+}
+
+/// How the fee of a cross shard transaction is shared between the shards it touches
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeeSplit {
+    /// Every shard gets the same share
+    Equal,
+    /// The first shard of the transaction gets the whole fee
+    First,
+}
+
+/// Crashes the `vn`th VN (counting from 0) at `at`. While crashed it drops every message and does
+/// nothing, and if `recover_at` is set it picks up again from the state it crashed in.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Fault {
+    pub vn: usize,
+    #[serde(with = "humantime_serde")]
+    pub at: Duration,
+    #[serde(default, with = "humantime_serde")]
+    pub recover_at: Option<Duration>,
+}
+
+/// Everything that determines how a simulation behaves. The binary exposes all of these as
+/// command line flags, with the same defaults as `SimulationConfig::default()`.
+#[derive(Args, Debug, Clone)]
+pub struct SimulationConfig {
+    /// Seed for all randomness in the simulation. A random seed is used and printed if not set
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    #[clap(short, long, default_value = "20")]
+    pub num_vns: usize,
+    #[clap(long, default_value = "50ms")]
+    pub min_latency: humantime::Duration,
+    #[clap(long, default_value = "50ms")]
+    pub max_latency: humantime::Duration,
+    /// Most bytes of transactions a block can carry, counted with `Transaction::size`. A block
+    /// always takes at least one transaction
+    #[clap(long, default_value = "1048576")]
+    pub max_block_size: u64,
+    #[clap(long, default_value = "5")]
+    pub num_shards: u32,
+    #[clap(long, value_enum, default_value = "round-robin")]
+    pub committee_assignment: CommitteeAssignment,
+    /// The fraction of VNs assumed to be Byzantine when working out the chance that a committee
+    /// is compromised. It does not change how the VNs behave
+    #[clap(long, default_value = "0.2")]
+    pub byzantine_fraction: f64,
+    /// How many random placements of the Byzantine VNs to try when estimating that chance
+    #[clap(long, default_value = "10000")]
+    pub safety_trials: usize,
+
+    ///  The time before deciding a block has timed out
+    #[clap(long, default_value = "5000ms")]
+    pub delta: humantime::Duration,
+    /// How long a transaction may wait in `waiting_prepared` for the other shards before the
+    /// leader retries it or precommits it as aborted. Zero never times out
+    #[clap(long, default_value = "10s")]
+    pub prepare_timeout: humantime::Duration,
+    /// How long a transaction may wait in `waiting_pre_committed` for the other shards. Every
+    /// shard has prepared it to commit by then, so it is retried but never aborted. Zero never
+    /// times out
+    #[clap(long, default_value = "10s")]
+    pub precommit_timeout: humantime::Duration,
+    /// How many times a timed out prepare is retried before it is aborted. A retry sends this
+    /// shard's block again to the shards that have not reached the phase yet
+    #[clap(long, default_value = "1")]
+    pub timeout_retries: u32,
+
+    /// The simulation runs for `num_steps * time_per_step`
+    #[clap(long, default_value = "40")]
+    pub num_steps: usize,
+    /// How often the mempool backlog is sampled. Events themselves happen at millisecond precision
+    #[clap(long, default_value = "100ms")]
+    pub time_per_step: humantime::Duration,
+
+    /// Stop as soon as every transaction has been generated and committed in all of its shards
+    #[clap(long)]
+    pub until_quiescent: bool,
+    /// The longest simulated time to run for, instead of `num_steps * time_per_step`
+    #[clap(long)]
+    pub max_time: Option<humantime::Duration>,
+
+    /// Print the stats of every VN after this many steps
+    #[clap(long, default_value = "10")]
+    pub print_stats_every: usize,
+    /// How often to build the wait-for graph between shards during the run. Zero only builds it
+    /// when the run ends
+    #[clap(long, default_value = "1s")]
+    pub wait_for_interval: humantime::Duration,
+    /// A transaction that has waited this long on another shard is reported as starved
+    #[clap(long, default_value = "2s")]
+    pub starvation_threshold: humantime::Duration,
+
+    #[clap(long, default_value = "5")]
+    pub max_tx_per_step_per_block: usize,
+
+    #[clap(long, default_value = "500")]
+    pub num_transactions: usize,
+    /// Queue transactions at this rate instead of all at the start
+    #[clap(long)]
+    pub tx_per_second: Option<f64>,
+
+    /// The mean time a VN spends executing a transaction before it can vote on or propose the
+    /// block that prepares it
+    #[clap(long, default_value = "1ms")]
+    pub execution_time: humantime::Duration,
+    #[clap(long, value_enum, default_value = "fixed")]
+    pub execution_time_distribution: ExecutionTimeDistribution,
+    /// The percentage of transactions that call a heavy contract
+    #[clap(long, default_value = "0")]
+    pub heavy_tx_percent: u32,
+    /// The mean execution time of a heavy contract call
+    #[clap(long, default_value = "50ms")]
+    pub heavy_execution_time: humantime::Duration,
+
+    /// The percentage of transactions whose execution fails, so that every shard aborts them
+    #[clap(long, default_value = "0")]
+    pub tx_failure_percent: u32,
+
+    /// Peak memory of a transaction while it executes, in MB
+    #[clap(long, default_value = "64")]
+    pub tx_memory: u64,
+    /// Peak memory of a heavy contract call while it executes, in MB
+    #[clap(long, default_value = "512")]
+    pub heavy_tx_memory: u64,
+    /// How many transactions a VN can execute at once, if they fit in its memory
+    #[clap(long, default_value = "8")]
+    pub execution_threads: usize,
+    /// The memory each VN has for executing transactions, in MB
+    #[clap(long, default_value = "2048")]
+    pub vn_memory: u64,
+
+    /// How many substates transactions pick their inputs from
+    #[clap(long, default_value = "1000000")]
+    pub num_substates: u64,
+    /// The percentage of transactions that also take one hot substate as an input, like a
+    /// popular DEX pool
+    #[clap(long, default_value = "0")]
+    pub hot_substate_percent: u32,
+    /// Percent chance that a transaction takes 2 inputs. Each input lives on a random shard, so a
+    /// transaction can touch fewer shards than it has inputs
+    #[clap(long, default_value = "10")]
+    pub probability_2_shards: u32,
+    /// Percent chance that a transaction takes 3 inputs, if it did not take 2 or 5
+    #[clap(long, default_value = "5")]
+    pub probability_3_shards: u32,
+    /// Percent chance that a transaction takes 4 inputs, if it did not take 2, 3 or 5
+    #[clap(long, default_value = "0")]
+    pub probability_4_shards: u32,
+    /// Percent chance that a transaction takes 5 inputs, drawn before the others
+    #[clap(long, default_value = "0")]
+    pub probability_5_shards: u32,
+
+    /// How the fee of a transaction is shared between its shards when it commits
+    #[clap(long, value_enum, default_value = "equal")]
+    pub fee_split: FeeSplit,
+    /// The fraction of a shard's share of a fee that goes to the leader of the block that commits
+    /// it. The rest is shared equally by the VNs whose votes formed the block's QC
+    #[clap(long, default_value = "0.5")]
+    pub leader_fee_share: f64,
+
+    /// CPU time to check one signature on a message, transaction or QC
+    #[clap(long, default_value = "50us")]
+    pub signature_check_time: humantime::Duration,
+    /// What it costs a VN to send or receive a MB, in the same unit as fees
+    #[clap(long, default_value = "10")]
+    pub cost_per_mb: f64,
+    /// What a second of CPU time costs a VN, in the same unit as fees
+    #[clap(long, default_value = "50")]
+    pub cost_per_cpu_second: f64,
+    /// What it costs a VN to store a MB of blocks for the run, in the same unit as fees
+    #[clap(long, default_value = "20")]
+    pub cost_per_mb_stored: f64,
+
+    /// VNs to crash during the run. Only set from a scenario file or the builder
+    #[clap(skip)]
+    pub faults: Vec<Fault>,
+
+    /// The length of the windows that TPS is measured over
+    #[clap(long, default_value = "1s")]
+    pub tps_window: humantime::Duration,
+    /// How far each TPS window slides along
+    #[clap(long, default_value = "100ms")]
+    pub tps_window_step: humantime::Duration,
+    /// Leave the start of the run out of the steady state TPS
+    #[clap(long, default_value = "0ms")]
+    pub warmup: humantime::Duration,
+}
+
+impl SimulationConfig {
+    /// Sets the field called `name`, in snake or kebab case, from the same text its command line
+    /// flag takes
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        fn parse<T: FromStr>(value: &str) -> Result<T, String>
+        where
+            T::Err: Display,
+        {
+            value
+                .parse()
+                .map_err(|e| format!("invalid value {}: {}", value, e))
+        }
+
+        match name.replace('-', "_").as_str() {
+            "seed" => self.seed = Some(parse(value)?),
+            "num_vns" => self.num_vns = parse(value)?,
+            "min_latency" => self.min_latency = parse(value)?,
+            "max_latency" => self.max_latency = parse(value)?,
+            "max_block_size" => self.max_block_size = parse(value)?,
+            "num_shards" => self.num_shards = parse(value)?,
+            "committee_assignment" => {
+                self.committee_assignment = CommitteeAssignment::from_str(value, true)?
+            }
+            "byzantine_fraction" => self.byzantine_fraction = parse(value)?,
+            "safety_trials" => self.safety_trials = parse(value)?,
+            "delta" => self.delta = parse(value)?,
+            "prepare_timeout" => self.prepare_timeout = parse(value)?,
+            "precommit_timeout" => self.precommit_timeout = parse(value)?,
+            "timeout_retries" => self.timeout_retries = parse(value)?,
+            "num_steps" => self.num_steps = parse(value)?,
+            "time_per_step" => self.time_per_step = parse(value)?,
+            "until_quiescent" => self.until_quiescent = parse(value)?,
+            "max_time" => self.max_time = Some(parse(value)?),
+            "wait_for_interval" => self.wait_for_interval = parse(value)?,
+            "starvation_threshold" => self.starvation_threshold = parse(value)?,
+            "max_tx_per_step_per_block" => self.max_tx_per_step_per_block = parse(value)?,
+            "num_transactions" => self.num_transactions = parse(value)?,
+            "tx_per_second" => self.tx_per_second = Some(parse(value)?),
+            "execution_time" => self.execution_time = parse(value)?,
+            "execution_time_distribution" => {
+                self.execution_time_distribution = ExecutionTimeDistribution::from_str(value, true)?
+            }
+            "heavy_tx_percent" => self.heavy_tx_percent = parse(value)?,
+            "heavy_execution_time" => self.heavy_execution_time = parse(value)?,
+            "tx_failure_percent" => self.tx_failure_percent = parse(value)?,
+            "tx_memory" => self.tx_memory = parse(value)?,
+            "heavy_tx_memory" => self.heavy_tx_memory = parse(value)?,
+            "execution_threads" => self.execution_threads = parse(value)?,
+            "vn_memory" => self.vn_memory = parse(value)?,
+            "num_substates" => self.num_substates = parse(value)?,
+            "hot_substate_percent" => self.hot_substate_percent = parse(value)?,
+            "probability_2_shards" => self.probability_2_shards = parse(value)?,
+            "probability_3_shards" => self.probability_3_shards = parse(value)?,
+            "probability_4_shards" => self.probability_4_shards = parse(value)?,
+            "probability_5_shards" => self.probability_5_shards = parse(value)?,
+            "fee_split" => self.fee_split = FeeSplit::from_str(value, true)?,
+            "leader_fee_share" => self.leader_fee_share = parse(value)?,
+            "signature_check_time" => self.signature_check_time = parse(value)?,
+            "cost_per_mb" => self.cost_per_mb = parse(value)?,
+            "cost_per_cpu_second" => self.cost_per_cpu_second = parse(value)?,
+            "cost_per_mb_stored" => self.cost_per_mb_stored = parse(value)?,
+            "tps_window" => self.tps_window = parse(value)?,
+            "tps_window_step" => self.tps_window_step = parse(value)?,
+            "warmup" => self.warmup = parse(value)?,
+            _ => return Err(format!("unknown parameter {}", name)),
+        }
+        Ok(())
+    }
+
+    /// Checks the values that the simulation cannot run with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.num_shards == 0 {
+            return Err("num_shards must be at least 1".to_string());
+        }
+        if *self.min_latency > *self.max_latency {
+            return Err(format!(
+                "min_latency ({}) must not be more than max_latency ({})",
+                self.min_latency, self.max_latency
+            ));
+        }
+        if self.num_substates == 0 {
+            return Err("num_substates must be at least 1".to_string());
+        }
+        if self.num_vns < self.num_shards as usize {
+            return Err(format!(
+                "num_vns ({}) must be at least num_shards ({}) so that every shard has a committee",
+                self.num_vns, self.num_shards
+            ));
+        }
+        Ok(())
+    }
+
+    /// How long the run lasts if it does not stop early, in ms
+    pub fn end_time(&self) -> u128 {
+        self.max_time
+            .map(|t| t.as_millis())
+            .unwrap_or(self.time_per_step.as_millis() * self.num_steps as u128)
+    }
+}
+
+// Parses an empty command line so that the defaults only live in the attributes above
+#[derive(Parser)]
+struct DefaultConfig {
+    #[clap(flatten)]
+    config: SimulationConfig,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        DefaultConfig::parse_from(["dansim"]).config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(name: &str, value: &str) -> Result<(), String> {
+        let mut config = SimulationConfig::default();
+        config.set(name, value)?;
+        config.validate()
+    }
+
+    #[test]
+    fn the_defaults_are_valid() {
+        SimulationConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_configs_the_simulation_cannot_run() {
+        assert!(validate("num_shards", "0").is_err());
+        assert!(validate("num_substates", "0").is_err());
+        assert!(validate("num_vns", "4").is_err());
+        assert!(validate("min_latency", "80ms").is_err());
+        assert!(validate("max_latency", "20ms").is_err());
+        assert!(validate("max_latency", "80ms").is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::metrics::Metrics;
+use crate::substate::SubstateAddress;
+use crate::transaction::Shard;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// How many of the transactions that touch a shard were aborted, and how many of those aborts it
+/// caused itself
+#[derive(Debug, Clone)]
+pub struct ShardContention {
+    pub shard: Shard,
+    pub transactions: usize,
+    /// Transactions that touch the shard and were aborted by any shard
+    pub aborted: usize,
+    /// Transactions this shard found a lock conflict for
+    pub conflicts: usize,
+}
+
+/// A substate that conflicting transactions were aborted on
+#[derive(Debug, Clone)]
+pub struct HotSubstate {
+    pub address: SubstateAddress,
+    pub shard: Shard,
+    pub aborted: usize,
+}
+
+/// How often transactions were aborted because another transaction had their inputs locked
+#[derive(Debug, Clone)]
+pub struct ContentionReport {
+    pub queued: usize,
+    pub aborted: usize,
+    pub shards: Vec<ShardContention>,
+    /// The substates with the most aborts, most first
+    pub hottest: Vec<HotSubstate>,
+}
+
+impl ContentionReport {
+    /// How many hot substates are reported
+    const HOTTEST: usize = 5;
+
+    pub fn from_metrics(metrics: &Metrics) -> Self {
+        let aborted: HashSet<u32> = metrics.lock_conflicts.iter().map(|c| c.tx_id).collect();
+
+        let mut shards: BTreeMap<Shard, ShardContention> = BTreeMap::new();
+        for shard in &metrics.shards {
+            shards.insert(
+                Shard(*shard),
+                ShardContention {
+                    shard: Shard(*shard),
+                    transactions: 0,
+                    aborted: 0,
+                    conflicts: 0,
+                },
+            );
+        }
+        for record in metrics.transactions.values() {
+            for shard in &record.tx.shards {
+                if let Some(contention) = shards.get_mut(shard) {
+                    contention.transactions += 1;
+                    if aborted.contains(&record.tx.id) {
+                        contention.aborted += 1;
+                    }
+                }
+            }
+        }
+
+        // A transaction can be left out by more than one leader before the abort is applied, so
+        // only count it once per shard and substate
+        let mut found: HashSet<(u32, Shard)> = HashSet::new();
+        let mut by_address: HashMap<(SubstateAddress, Shard), HashSet<u32>> = HashMap::new();
+        for conflict in &metrics.lock_conflicts {
+            if found.insert((conflict.tx_id, conflict.shard)) {
+                if let Some(contention) = shards.get_mut(&conflict.shard) {
+                    contention.conflicts += 1;
+                }
+            }
+            by_address
+                .entry((conflict.address, conflict.shard))
+                .or_default()
+                .insert(conflict.tx_id);
+        }
+        let mut hottest: Vec<_> = by_address
+            .into_iter()
+            .map(|((address, shard), txs)| HotSubstate {
+                address,
+                shard,
+                aborted: txs.len(),
+            })
+            .collect();
+        hottest.sort_by(|a, b| b.aborted.cmp(&a.aborted).then(a.address.cmp(&b.address)));
+        hottest.truncate(Self::HOTTEST);
+
+        Self {
+            queued: metrics.transactions.len(),
+            aborted: aborted.len(),
+            shards: shards.into_values().collect(),
+            hottest,
+        }
+    }
+
+    /// The fraction of queued transactions that were aborted
+    pub fn abort_rate(&self) -> f64 {
+        self.aborted as f64 / self.queued.max(1) as f64
+    }
+
+    pub fn print(&self) {
+        println!("==== Lock contention ====");
+        println!(
+            "Voted to abort on a locked input: {} of {} transactions ({:.2}%)",
+            self.aborted,
+            self.queued,
+            self.abort_rate() * 100.0
+        );
+        println!(
+            "  {:>5} {:>8} {:>8} {:>8} {:>10}",
+            "shard", "txs", "aborted", "rate", "conflicts"
+        );
+        for shard in &self.shards {
+            println!(
+                "  {:>5} {:>8} {:>8} {:>7.2}% {:>10}",
+                shard.shard.0,
+                shard.transactions,
+                shard.aborted,
+                shard.aborted as f64 * 100.0 / shard.transactions.max(1) as f64,
+                shard.conflicts
+            );
+        }
+        if !self.hottest.is_empty() {
+            println!("Most contended substates:");
+            for hot in &self.hottest {
+                println!(
+                    "  {:#018x} (shard {}): {} aborted",
+                    hot.address.0, hot.shard.0, hot.aborted
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+    use crate::simulation::{SimulationBuilder, SimulationResult};
+    use std::time::Duration;
+
+    async fn run(hot_substate_percent: u32) -> SimulationResult {
+        let config = SimulationConfig {
+            seed: Some(9),
+            num_vns: 8,
+            num_shards: 2,
+            num_transactions: 40,
+            hot_substate_percent,
+            until_quiescent: true,
+            max_time: Some(Duration::from_secs(60).into()),
+            ..Default::default()
+        };
+        SimulationBuilder::from_config(config).build().run().await
+    }
+
+    #[tokio::test]
+    async fn the_hot_substate_comes_out_hottest() {
+        let result = run(50).await;
+        let report = result.contention_report();
+        let hot = SubstateAddress::from_index(0, result.config.num_substates);
+        let hot_shard = hot.shard(result.config.num_shards);
+
+        assert!(report.aborted > 0);
+        assert!(report.abort_rate() > 0.0);
+        let hottest = &report.hottest[0];
+        assert_eq!((hottest.address, hottest.shard), (hot, hot_shard));
+        assert!(hottest.aborted > 0);
+        let shard = report.shards.iter().find(|s| s.shard == hot_shard).unwrap();
+        assert!(shard.conflicts >= hottest.aborted);
+        assert!(shard.aborted >= shard.conflicts);
+    }
+
+    #[tokio::test]
+    async fn spread_out_inputs_do_not_conflict() {
+        let report = run(0).await.contention_report();
+        assert_eq!(report.aborted, 0);
+        assert!(report.hottest.is_empty());
+        assert!(report.shards.iter().all(|s| s.conflicts == 0));
+    }
+}
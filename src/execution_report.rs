@@ -0,0 +1,157 @@
+use crate::latency_report::Percentiles;
+use crate::metrics::TransactionPhase;
+use crate::simulation::SimulationResult;
+use crate::transaction::Shard;
+use std::collections::BTreeMap;
+
+/// How much time the VNs of a shard spent executing, next to its latency and throughput
+#[derive(Debug, Clone)]
+pub struct ShardExecution {
+    pub shard: Shard,
+    /// Execution time of the transactions that touch the shard, in microseconds
+    pub execution_time: Percentiles,
+    /// The fraction of the run that its VNs were busy executing, averaged and at most
+    pub mean_busy: f64,
+    pub max_busy: f64,
+    /// End to end latency in ms of the transactions that touch the shard
+    pub latency: Percentiles,
+    pub committed_tps: f64,
+}
+
+/// The execution time of transactions and how busy it kept each shard
+#[derive(Debug, Clone)]
+pub struct ExecutionReport {
+    pub execution_time: Percentiles,
+    pub shards: Vec<ShardExecution>,
+}
+
+impl ExecutionReport {
+    pub fn from_result(result: &SimulationResult) -> Self {
+        let transactions = &result.metrics.transactions;
+        let end_time = result.end_time.max(1) as f64;
+        let throughput = result.throughput_report().steady_state;
+
+        let mut busy: BTreeMap<Shard, Vec<f64>> = BTreeMap::new();
+        for vn in result.vns.values() {
+            let busy_time = result.stats.get(&vn.id).map(|s| s.busy_time).unwrap_or(0);
+            busy.entry(vn.shard)
+                .or_default()
+                .push(busy_time as f64 / end_time);
+        }
+
+        let shards = busy
+            .into_iter()
+            .map(|(shard, busy)| {
+                let touching = || {
+                    transactions
+                        .values()
+                        .filter(|t| t.tx.shards.contains(&shard))
+                };
+                ShardExecution {
+                    shard,
+                    execution_time: Percentiles::from_values(
+                        touching().map(|t| t.tx.execution_time as u128).collect(),
+                    ),
+                    mean_busy: busy.iter().sum::<f64>() / busy.len() as f64,
+                    max_busy: busy.iter().cloned().fold(0.0, f64::max),
+                    latency: Percentiles::from_values(
+                        touching()
+                            .filter_map(|t| {
+                                t.reached_in_all_shards(TransactionPhase::Committed)
+                                    .map(|c| c - t.queued_at)
+                            })
+                            .collect(),
+                    ),
+                    committed_tps: throughput
+                        .committed_per_shard
+                        .get(&shard)
+                        .cloned()
+                        .unwrap_or(0.0),
+                }
+            })
+            .collect();
+
+        Self {
+            execution_time: Percentiles::from_values(
+                transactions
+                    .values()
+                    .map(|t| t.tx.execution_time as u128)
+                    .collect(),
+            ),
+            shards,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("==== Execution ====");
+        println!(
+            "Execution time per tx (us): p50: {} p90: {} p99: {} max: {}",
+            self.execution_time.p50,
+            self.execution_time.p90,
+            self.execution_time.p99,
+            self.execution_time.max
+        );
+        println!(
+            "  {:>5} {:>10} {:>10} {:>8} {:>8} {:>10} {:>10} {:>8}",
+            "shard", "exec p50", "exec p99", "busy", "max busy", "p50 (ms)", "p99 (ms)", "tps"
+        );
+        for shard in &self.shards {
+            println!(
+                "  {:>5} {:>10} {:>10} {:>7.1}% {:>7.1}% {:>10} {:>10} {:>8.2}",
+                shard.shard.0,
+                shard.execution_time.p50,
+                shard.execution_time.p99,
+                shard.mean_busy * 100.0,
+                shard.max_busy * 100.0,
+                shard.latency.p50,
+                shard.latency.p99,
+                shard.committed_tps
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+    use crate::simulation::SimulationBuilder;
+    use std::time::Duration;
+
+    async fn run(execution_time: Duration) -> SimulationResult {
+        let config = SimulationConfig {
+            seed: Some(6),
+            num_vns: 4,
+            num_shards: 1,
+            num_transactions: 20,
+            probability_2_shards: 0,
+            probability_3_shards: 0,
+            execution_time: execution_time.into(),
+            execution_threads: 1,
+            until_quiescent: true,
+            max_time: Some(Duration::from_secs(60).into()),
+            ..Default::default()
+        };
+        SimulationBuilder::from_config(config).build().run().await
+    }
+
+    #[tokio::test]
+    async fn busy_vns_hold_back_the_shard() {
+        let quick = run(Duration::from_millis(1)).await;
+        let slow = run(Duration::from_millis(100)).await;
+        assert!(quick.quiescent && slow.quiescent);
+
+        let report = slow.execution_report();
+        assert_eq!(report.execution_time.p50, 100_000);
+        let shard = &report.shards[0];
+        assert!(shard.mean_busy > 0.0 && shard.max_busy <= 1.0);
+        // With one thread, each VN spends at least 100ms on every transaction
+        for stats in slow.stats.values() {
+            assert!(stats.busy_time >= 100 * stats.transactions_executed as u128);
+        }
+        // The leader cannot propose until it has executed, so transactions wait behind each other
+        let latency = |r: &SimulationResult| r.execution_report().shards[0].latency.p50;
+        assert!(latency(&slow) > latency(&quick) + 100);
+        assert!(slow.end_time > quick.end_time + 20 * 100);
+    }
+}
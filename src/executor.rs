@@ -0,0 +1,179 @@
+use crate::memory_report::MemoryUsage;
+
+/// One transaction running on a VN, with times in microseconds
+#[derive(Debug, Clone)]
+struct Execution {
+    start: u64,
+    end: u64,
+    memory: u64,
+}
+
+/// What happened when a batch of transactions was executed
+#[derive(Debug, Clone, Default)]
+pub struct BatchOutcome {
+    /// When the last transaction finished, in microseconds
+    pub end: u64,
+    /// The most memory in use at once
+    pub peak_memory: u64,
+    /// The most memory the batch would have used at once if memory were unlimited
+    pub memory_demand: u64,
+    /// Microseconds that transactions waited for memory while a thread was free
+    pub memory_stall: u64,
+    /// Transactions that need more memory than the VN has, and so ran on their own
+    pub over_budget: u64,
+}
+
+/// Runs transactions on a fixed number of threads, as long as the memory they need together fits
+/// in the VN's budget. Transactions start in the order they are given.
+#[derive(Debug, Clone)]
+pub struct Executor {
+    threads: usize,
+    memory_budget: u64,
+    executions: Vec<Execution>,
+}
+
+impl Executor {
+    pub fn new(threads: usize, memory_budget: u64) -> Self {
+        Self {
+            threads: threads.max(1),
+            memory_budget,
+            executions: vec![],
+        }
+    }
+
+    /// Executes `txs`, given as execution time and memory, starting at `start`. Nothing else may
+    /// be running by then.
+    pub fn execute(&mut self, start: u64, txs: &[(u64, u64)]) -> BatchOutcome {
+        let mut outcome = BatchOutcome {
+            end: start,
+            ..Default::default()
+        };
+        let mut running: Vec<(u64, u64)> = vec![];
+        let mut t = start;
+        for (duration, memory) in txs {
+            // A transaction bigger than the whole budget has to run alone
+            let needed = if *memory > self.memory_budget {
+                outcome.over_budget += 1;
+                self.memory_budget
+            } else {
+                *memory
+            };
+            loop {
+                running.retain(|(end, _)| *end > t);
+                let used: u64 = running.iter().map(|(_, m)| m).sum();
+                let threads_free = running.len() < self.threads;
+                if threads_free && used + needed <= self.memory_budget {
+                    outcome.peak_memory = outcome.peak_memory.max(used + memory);
+                    break;
+                }
+                let next = running.iter().map(|(end, _)| *end).min().unwrap_or(t);
+                if threads_free {
+                    outcome.memory_stall += next - t;
+                }
+                t = next;
+            }
+            running.push((t + duration, needed));
+            self.executions.push(Execution {
+                start: t,
+                end: t + duration,
+                memory: *memory,
+            });
+            outcome.end = outcome.end.max(t + duration);
+        }
+        outcome.memory_demand = self.demand(start, txs);
+        outcome
+    }
+
+    /// The peak memory of running `txs` with only the number of threads as a limit
+    fn demand(&self, start: u64, txs: &[(u64, u64)]) -> u64 {
+        let mut running: Vec<(u64, u64)> = vec![];
+        let mut peak = 0;
+        let mut t = start;
+        for (duration, memory) in txs {
+            if running.len() >= self.threads {
+                t = running.iter().map(|(end, _)| *end).min().unwrap_or(t);
+            }
+            running.retain(|(end, _)| *end > t);
+            running.push((t + duration, *memory));
+            peak = peak.max(running.iter().map(|(_, m)| m).sum());
+        }
+        peak
+    }
+
+    /// The memory in use between `from` and `to`, in microseconds. Anything that finished before
+    /// `to` is forgotten, so windows have to be asked for in order.
+    pub fn usage(&mut self, from: u64, to: u64) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        if to > from {
+            let mut points: Vec<(u64, i64)> = vec![];
+            let mut integral = 0.0;
+            for e in &self.executions {
+                let (start, end) = (e.start.max(from), e.end.min(to));
+                if start < end {
+                    points.push((start, e.memory as i64));
+                    points.push((end, -(e.memory as i64)));
+                    integral += (end - start) as f64 * e.memory as f64;
+                }
+            }
+            // Ends sort before starts at the same time
+            points.sort();
+            let mut in_use = 0i64;
+            for (_, change) in points {
+                in_use += change;
+                usage.peak = usage.peak.max(in_use as u64);
+            }
+            usage.mean = integral / (to - from) as f64;
+        }
+        self.executions.retain(|e| e.end > to);
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threads_limit_how_many_run_at_once() {
+        let mut executor = Executor::new(2, 1000);
+        let outcome = executor.execute(100, &[(10, 1), (10, 1), (10, 1)]);
+        assert_eq!(outcome.end, 120);
+        assert_eq!(outcome.peak_memory, 2);
+        assert_eq!(outcome.memory_stall, 0);
+        assert_eq!(outcome.over_budget, 0);
+    }
+
+    #[test]
+    fn transactions_wait_for_memory() {
+        let mut executor = Executor::new(4, 100);
+        let outcome = executor.execute(0, &[(10, 60), (10, 60)]);
+        // The second waits for the first although a thread is free
+        assert_eq!(outcome.end, 20);
+        assert_eq!(outcome.peak_memory, 60);
+        assert_eq!(outcome.memory_demand, 120);
+        assert_eq!(outcome.memory_stall, 10);
+    }
+
+    #[test]
+    fn a_transaction_over_budget_runs_alone() {
+        let mut executor = Executor::new(4, 100);
+        let outcome = executor.execute(0, &[(10, 10), (10, 150), (10, 10)]);
+        assert_eq!(outcome.over_budget, 1);
+        assert_eq!(outcome.end, 30);
+        assert_eq!(outcome.peak_memory, 150);
+    }
+
+    #[test]
+    fn usage_covers_each_window_in_turn() {
+        let mut executor = Executor::new(2, 100);
+        executor.execute(0, &[(10, 40), (20, 20)]);
+        let first = executor.usage(0, 10);
+        assert_eq!(first.peak, 60);
+        assert_eq!(first.mean, 60.0);
+        let second = executor.usage(10, 30);
+        assert_eq!(second.peak, 20);
+        // 20 in use for half of the window
+        assert_eq!(second.mean, 10.0);
+        assert_eq!(executor.usage(30, 40).peak, 0);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::metrics::{Metrics, TransactionPhase, TransactionRecord};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// How long each phase of a transaction took, in ms. A phase is reached once every involved
+/// shard has reached it, so a phase that did not finish before the end of the run is `None`.
+#[derive(Debug, Clone)]
+pub struct TransactionLatency {
+    pub tx_id: u32,
+    pub num_shards: usize,
+    pub queued_to_prepared: Option<u128>,
+    pub prepared_to_precommitted: Option<u128>,
+    pub precommitted_to_committed: Option<u128>,
+    pub end_to_end: Option<u128>,
+}
+
+impl TransactionLatency {
+    pub fn from_record(record: &TransactionRecord) -> Self {
+        let prepared = record.reached_in_all_shards(TransactionPhase::PreparedReady);
+        let precommitted = record.reached_in_all_shards(TransactionPhase::PrecommitReady);
+        let committed = record.reached_in_all_shards(TransactionPhase::Committed);
+        let between = |from: Option<u128>, to: Option<u128>| match (from, to) {
+            (Some(from), Some(to)) => Some(to.saturating_sub(from)),
+            _ => None,
+        };
+        Self {
+            tx_id: record.tx.id,
+            num_shards: record.tx.shards.len(),
+            queued_to_prepared: between(Some(record.queued_at), prepared),
+            prepared_to_precommitted: between(prepared, precommitted),
+            precommitted_to_committed: between(precommitted, committed),
+            end_to_end: between(Some(record.queued_at), committed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Percentiles {
+    pub count: usize,
+    pub p50: u128,
+    pub p90: u128,
+    pub p99: u128,
+    pub max: u128,
+}
+
+impl Percentiles {
+    /// Nearest rank percentiles of `values`
+    pub fn from_values(mut values: Vec<u128>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_unstable();
+        let rank = |p: usize| values[(p * values.len()).div_ceil(100).max(1) - 1];
+        Self {
+            count: values.len(),
+            p50: rank(50),
+            p90: rank(90),
+            p99: rank(99),
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PhaseLatencies {
+    pub queued_to_prepared: Percentiles,
+    pub prepared_to_precommitted: Percentiles,
+    pub precommitted_to_committed: Percentiles,
+    pub end_to_end: Percentiles,
+}
+
+impl PhaseLatencies {
+    fn from_latencies<'a, I: Iterator<Item = &'a TransactionLatency> + Clone>(
+        latencies: I,
+    ) -> Self {
+        let collect = |f: fn(&TransactionLatency) -> Option<u128>| {
+            Percentiles::from_values(latencies.clone().filter_map(f).collect())
+        };
+        Self {
+            queued_to_prepared: collect(|l| l.queued_to_prepared),
+            prepared_to_precommitted: collect(|l| l.prepared_to_precommitted),
+            precommitted_to_committed: collect(|l| l.precommitted_to_committed),
+            end_to_end: collect(|l| l.end_to_end),
+        }
+    }
+
+    fn print(&self) {
+        for (name, p) in [
+            ("queued->prepared", &self.queued_to_prepared),
+            ("prepared->precommitted", &self.prepared_to_precommitted),
+            ("precommitted->committed", &self.precommitted_to_committed),
+            ("end to end", &self.end_to_end),
+        ] {
+            println!(
+                "  {:<24} n: {:<6} p50: {:<6} p90: {:<6} p99: {:<6} max: {}",
+                name, p.count, p.p50, p.p90, p.p99, p.max
+            );
+        }
+    }
+}
+
+/// Per transaction phase latencies, summarised overall and by the number of shards involved.
+/// Aborted transactions are left out, as their phases say nothing about how long a commit takes
+#[derive(Debug, Clone)]
+pub struct LatencyReport {
+    pub transactions: Vec<TransactionLatency>,
+    pub overall: PhaseLatencies,
+    pub by_num_shards: BTreeMap<usize, PhaseLatencies>,
+}
+
+impl LatencyReport {
+    pub fn from_metrics(metrics: &Metrics) -> Self {
+        let transactions: Vec<_> = metrics
+            .transactions
+            .values()
+            .filter(|t| t.abort_reason.is_none())
+            .map(TransactionLatency::from_record)
+            .collect();
+        let overall = PhaseLatencies::from_latencies(transactions.iter());
+        let mut by_num_shards = BTreeMap::new();
+        for num_shards in transactions.iter().map(|t| t.num_shards) {
+            by_num_shards.entry(num_shards).or_insert_with(|| {
+                PhaseLatencies::from_latencies(
+                    transactions.iter().filter(|t| t.num_shards == num_shards),
+                )
+            });
+        }
+        Self {
+            transactions,
+            overall,
+            by_num_shards,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("==== Latency (ms) ====");
+        println!("All transactions:");
+        self.overall.print();
+        for (num_shards, latencies) in &self.by_num_shards {
+            println!("{} shard(s):", num_shards);
+            latencies.print();
+        }
+    }
+
+    /// Writes the latencies of every transaction as CSV. Phases that were not reached are left
+    /// empty.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(
+            file,
+            "tx_id,num_shards,queued_to_prepared,prepared_to_precommitted,precommitted_to_committed,end_to_end"
+        )?;
+        let cell = |v: Option<u128>| v.map(|v| v.to_string()).unwrap_or_default();
+        for t in &self.transactions {
+            writeln!(
+                file,
+                "{},{},{},{},{},{}",
+                t.tx_id,
+                t.num_shards,
+                cell(t.queued_to_prepared),
+                cell(t.prepared_to_precommitted),
+                cell(t.precommitted_to_committed),
+                cell(t.end_to_end)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Simulation;
+    use std::time::Duration;
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let p = Percentiles::from_values((1..=10).rev().collect());
+        assert_eq!((p.count, p.p50, p.p90, p.p99, p.max), (10, 5, 9, 10, 10));
+
+        let p = Percentiles::from_values((1..=200).collect());
+        assert_eq!((p.p50, p.p90, p.p99, p.max), (100, 180, 198, 200));
+    }
+
+    #[test]
+    fn percentiles_of_no_values_are_zero() {
+        let p = Percentiles::from_values(vec![]);
+        assert_eq!((p.count, p.p50, p.p90, p.p99, p.max), (0, 0, 0, 0, 0));
+    }
+
+    #[test]
+    fn percentiles_of_one_value_are_that_value() {
+        let p = Percentiles::from_values(vec![7]);
+        assert_eq!((p.count, p.p50, p.p90, p.p99, p.max), (1, 7, 7, 7, 7));
+    }
+
+    #[tokio::test]
+    async fn every_committed_transaction_has_every_phase() {
+        let result = Simulation::builder()
+            .seed(1)
+            .num_vns(8)
+            .num_shards(4)
+            .workload(100, Some(200.0))
+            .cross_shard_probabilities([40, 20, 0, 0])
+            .until_quiescent(true)
+            .max_time(Duration::from_secs(60))
+            .build()
+            .run()
+            .await;
+        assert!(result.quiescent);
+        let committed = result.metrics.committed_transactions();
+        assert_eq!(committed.len(), 100);
+
+        let report = result.latency_report();
+        let counts = |l: &PhaseLatencies| {
+            [
+                l.queued_to_prepared.count,
+                l.prepared_to_precommitted.count,
+                l.precommitted_to_committed.count,
+                l.end_to_end.count,
+            ]
+        };
+        assert_eq!(counts(&report.overall), [committed.len(); 4]);
+        for (num_shards, latencies) in &report.by_num_shards {
+            let n = committed
+                .iter()
+                .filter(|t| t.tx.shards.len() == *num_shards)
+                .count();
+            assert_eq!(counts(latencies), [n; 4], "{} shard(s)", num_shards);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Simulates HotStuff consensus running in every shard of a Cerberus network, to measure
+//! throughput, latency and the cost of cross shard transactions.
+//!
+//! Configure a run with `Simulation::builder()`, add any `SimulationObserver`s, then `run` it to
+//! get a `SimulationResult` with the recorded metrics.
+
+pub mod block;
+mod committee_manager;
+pub mod committee_safety;
+pub mod committee_size;
+pub mod config;
+pub mod contention_report;
+pub mod execution_report;
+mod executor;
+mod id_provider;
+mod indexer;
+pub mod latency_report;
+pub mod max_tps;
+pub mod memory_report;
+pub mod message;
+pub mod metrics;
+pub mod neo4j_observer;
+mod network;
+mod network_connection;
+pub mod observer;
+pub mod profit;
+pub mod qc;
+pub mod replications;
+pub mod rewards;
+pub mod run_summary;
+pub mod safety_checker;
+pub mod scenario;
+mod scheduler;
+mod sim_rng;
+pub mod simulation;
+pub mod subscriber;
+pub mod substate;
+pub mod sweep;
+pub mod throughput;
+pub mod timeout_report;
+pub mod trace;
+pub mod transaction;
+mod transaction_generator;
+pub mod validator_node;
+pub mod wait_for;
+
+pub use config::SimulationConfig;
+pub use observer::SimulationObserver;
+pub use simulation::{Simulation, SimulationBuilder, SimulationResult};
@@ -1,164 +1,151 @@
-use crate::block::Block;
-use crate::committee_manager::CommitteeManager;
-use crate::id_provider::IdProvider;
-use crate::subscriber::Subscriber;
-use crate::transaction::Shard;
-use clap::Parser;
-use cli::Cli;
-use indexer::Indexer;
-use message::Message;
-use message_id_factory::MessageIdFactory;
-use network::Network;
-use network_connection::NetworkConnection;
-use node_factory::NodeFactory;
-use node_id::NodeId;
+use crate::cli::{Cli, Command};
+use clap::{CommandFactory, ErrorKind, FromArgMatches, ValueSource};
+use dansim::committee_safety::CommitteeSafety;
+use dansim::neo4j_observer::Neo4jObserver;
+use dansim::replications::Replications;
+use dansim::run_summary::default_jobs;
+use dansim::scenario::Scenario;
+use dansim::trace::JsonlTraceWriter;
+use dansim::SimulationBuilder;
+use dansim::{committee_size, max_tps, sweep};
 use rand::Rng;
-use std::collections::{HashMap, VecDeque};
-use std::fmt::{Display, Formatter};
-use std::process::id;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
-use tokio;
-use transaction::Transaction;
-use transaction_generator::TransactionGenerator;
-use validator_node::ValidatorNode;
 
-mod block;
-mod block_factory;
 mod cli;
-mod committee_manager;
-mod id_provider;
-mod indexer;
-mod message;
-mod message_id_factory;
-mod network;
-mod network_connection;
-mod node_factory;
-mod node_id;
-mod qc;
-mod subscriber;
-mod transaction;
-mod transaction_generator;
-mod validator_node;
 
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
-    let mut vns = HashMap::new();
-    let subscriber = Subscriber::connect().await;
-    let mut network = Network::new(subscriber.clone());
-    let id_provider = IdProvider::new();
-    let indexer = Indexer::new(id_provider.next());
-    subscriber.create_indexer(indexer.id).await;
-    let cli = Arc::new(cli);
-    let genesis = Arc::new(Block::genesis());
-    let committee_manager = CommitteeManager::new();
-    for s in 0..cli.num_shards {
-        subscriber.create_shard(s).await;
-    }
-    for i in 0..cli.num_vns {
-        let shard = Shard(i as u32 % cli.num_shards);
-        let latency = if cli.min_latency.as_millis() == cli.max_latency.as_millis() {
-            cli.min_latency.as_millis()
-        } else {
-            rand::thread_rng().gen_range(cli.min_latency.as_millis()..cli.max_latency.as_millis())
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(path) = cli.scenario.clone() {
+        let scenario = Scenario::load(&path)
+            .unwrap_or_else(|e| panic!("Could not load {}: {}", path.display(), e));
+        // The derived args are named after the fields in kebab case
+        let overridden = |field: &str| {
+            matches
+                .value_source(field.replace('_', "-").as_str())
+                .is_some_and(|source| source != ValueSource::DefaultValue)
         };
-        let vn = ValidatorNode::new(
-            id_provider.next(),
-            shard,
-            cli.clone(),
-            genesis.clone(),
-            id_provider.clone(),
-            committee_manager.clone(),
-            subscriber.clone(),
-            latency,
-        );
-        subscriber.create_vn(vn.id, vn.shard, latency).await;
-
-        network.add_connection(indexer.id, vn.id, latency, latency);
-        committee_manager.add_validator(vn.shard, vn.id).await;
-        vns.insert(vn.id, vn);
-    }
-
-    for (_, vn) in &vns {
-        for (_, vninner) in &vns {
-            if vn.id == vninner.id {
-                network.add_connection(vn.id, vninner.id, 0, 0);
-            } else {
-                network.add_connection(
-                    vn.id,
-                    vninner.id,
-                    (vn.base_latency + vninner.base_latency),
-                    (vn.base_latency + vninner.base_latency),
-                );
-            }
+        scenario.apply_to(&mut cli.simulation, overridden);
+        let output = scenario.output;
+        if !overridden("trace_file") {
+            cli.trace_file = cli.trace_file.or(output.trace_file);
+        }
+        if !overridden("latency_report") {
+            cli.latency_report = cli.latency_report.or(output.latency_report);
+        }
+        if !overridden("throughput_report") {
+            cli.throughput_report = cli.throughput_report.or(output.throughput_report);
+        }
+        if !overridden("memory_report") {
+            cli.memory_report = cli.memory_report.or(output.memory_report);
+        }
+        if !overridden("neo4j_uri") {
+            cli.neo4j_uri = cli.neo4j_uri.or(output.neo4j_uri);
         }
     }
-
-    let mut transaction_generator =
-        TransactionGenerator::new(id_provider.clone(), cli.num_transactions, cli.clone());
-    let mut curr_time = 0;
-    let time_step_millis = cli.time_per_step.as_millis();
-    let num_steps = cli.num_steps as u128;
-
-    loop {
-        println!("Time: {:?}", curr_time);
-        loop {
-            if let Some(transaction) = transaction_generator.next() {
-                let transaction = Arc::new(transaction);
-                subscriber
-                    .on_transaction_queued(transaction.id, curr_time, &transaction)
-                    .await;
-                for (vn_id, _) in &vns {
-                    let m = Message::Transaction {
-                        id: id_provider.next(),
-                        tx: transaction.clone(),
-                    };
-
-                    network.send_message(indexer.id, *vn_id, m, curr_time).await;
-                }
-            } else {
-                break;
+    if let Err(e) = cli.simulation.validate() {
+        Cli::command().error(ErrorKind::InvalidValue, e).exit();
+    }
+    let seed = *cli
+        .simulation
+        .seed
+        .get_or_insert_with(|| rand::thread_rng().gen());
+    println!("Seed: {}", seed);
+    match &cli.command {
+        Some(Command::MaxTps(args)) => {
+            if let Err(e) = max_tps::search(&cli.simulation, args).await {
+                Cli::command().error(ErrorKind::InvalidValue, e).exit();
             }
         }
-        loop {
-            let mut new_messages = false;
-            // loop because there are loop backs
-            let messages = network.update(curr_time);
-            for (to, message) in messages {
-                println!("Message: {} arrives at: {:?}", message, to);
-                vns.get_mut(&to)
-                    .expect("not found")
-                    .deliver_message(message, curr_time);
-            }
-            for (_, vn) in &mut vns {
-                let broadcasts = vn.update(curr_time).await;
-                if !broadcasts.is_empty() {
-                    new_messages = true;
-                }
-                for (to, message) in broadcasts {
-                    network.send_message(vn.id, to, message, curr_time).await;
-                }
-            }
-            if !new_messages {
-                break;
+        Some(Command::Sweep(args)) => {
+            if let Err(e) = sweep::run(&cli.simulation, args, cli.replications).await {
+                exit_with(e);
             }
         }
-        curr_time += time_step_millis;
-        if curr_time / time_step_millis % (cli.print_stats_every as u128) == 0 {
-            for (_, vn) in &vns {
-                vn.print_stats();
+        Some(Command::CommitteeSize(args)) => {
+            if let Err(e) = committee_size::run(&cli.simulation, args, cli.replications).await {
+                exit_with(e);
             }
-            subscriber.print_stats().await;
         }
-        if curr_time > time_step_millis * num_steps {
-            break;
+        Some(Command::CommitteeSafety) => CommitteeSafety::from_config(&cli.simulation).print(),
+        None if cli.replications > 1 => {
+            Replications::run(&cli.simulation, cli.replications, default_jobs())
+                .await
+                .print();
         }
+        None => run_once(&cli).await,
     }
+}
 
-    for (_, vn) in &vns {
-        vn.print_stats();
+/// Reports a config that cannot run as a usage error, and any other error as failing to write
+fn exit_with(e: std::io::Error) -> ! {
+    let kind = match e.kind() {
+        std::io::ErrorKind::InvalidInput => ErrorKind::InvalidValue,
+        _ => ErrorKind::Io,
+    };
+    Cli::command().error(kind, e).exit()
+}
+
+async fn run_once(cli: &Cli) {
+    let mut builder = SimulationBuilder::from_config(cli.simulation.clone());
+    if let Some(uri) = &cli.neo4j_uri {
+        match Neo4jObserver::connect(uri, &cli.neo4j_user, &cli.neo4j_password).await {
+            Ok(observer) => builder = builder.observer(Arc::new(observer)),
+            Err(e) => Cli::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    format!("Could not connect to Neo4j at {}: {}", uri, e),
+                )
+                .exit(),
+        }
+    }
+    if let Some(path) = &cli.trace_file {
+        builder = builder.observer(Arc::new(
+            JsonlTraceWriter::create(path).expect("Could not create trace file"),
+        ));
+    }
+    let result = builder.build().run().await;
+    if let Some(violation) = &result.safety_violation {
+        violation.print();
+        std::process::exit(1);
     }
 
-    subscriber.print_stats().await;
+    result.print_stats();
+    if cli.simulation.until_quiescent {
+        if result.quiescent {
+            println!("Quiescent at {}ms", result.end_time);
+        } else {
+            result.print_stuck();
+        }
+    }
+    result.metrics.print_summary();
+    result.contention_report().print();
+    result.timeout_report().print();
+    result.wait_for_report().print();
+    result.execution_report().print();
+    let memory_report = result.memory_report();
+    memory_report.print();
+    if let Some(path) = &cli.memory_report {
+        memory_report
+            .write_csv(path)
+            .expect("Could not write memory report");
+    }
+    CommitteeSafety::from_config(&result.config).print();
+    result.profit_report().print();
+    let latency_report = result.latency_report();
+    latency_report.print();
+    if let Some(path) = &cli.latency_report {
+        latency_report
+            .write_csv(path)
+            .expect("Could not write latency report");
+    }
+    let throughput_report = result.throughput_report();
+    throughput_report.print();
+    if let Some(path) = &cli.throughput_report {
+        throughput_report
+            .write_csv(path)
+            .expect("Could not write throughput report");
+    }
 }
@@ -0,0 +1,259 @@
+use crate::config::SimulationConfig;
+use crate::latency_report::Percentiles;
+use crate::metrics::TransactionPhase;
+use crate::simulation::SimulationBuilder;
+use clap::Args;
+
+#[derive(Args, Debug, Clone)]
+pub struct MaxTpsArgs {
+    /// The offered load of the first run
+    #[clap(long, default_value = "10")]
+    pub start_tps: f64,
+    /// Multiply the offered load by this much after each sustainable run
+    #[clap(long, default_value = "2")]
+    pub growth: f64,
+    /// Give up searching above this load
+    #[clap(long, default_value = "10000")]
+    pub max_tps: f64,
+    /// How many times to halve the gap between the last sustainable and first unsustainable load
+    #[clap(long, default_value = "4")]
+    pub refine_steps: usize,
+    /// A run is unsustainable if the mean mempool backlog in the last quarter of the run is this
+    /// fraction higher than in the second quarter, or the median age of transactions queued in
+    /// the third quarter is this fraction higher than of those queued in the second. Transactions
+    /// queued in the last quarter have not had the time to commit, so they are left out
+    #[clap(long, default_value = "0.5")]
+    pub max_growth: f64,
+}
+
+/// The outcome of a single run at a fixed offered load
+#[derive(Debug, Clone)]
+pub struct LoadRun {
+    pub offered_tps: f64,
+    pub committed_tps: f64,
+    /// Average backlog per VN in the second and last quarter of the run
+    pub backlog_mid: f64,
+    pub backlog_end: f64,
+    /// Median age of transactions queued in the second and third quarter of the run. A
+    /// transaction that has not committed is aged up to the end of the run.
+    pub latency_mid: u128,
+    pub latency_end: u128,
+    pub sustainable: bool,
+}
+
+impl LoadRun {
+    fn print(&self) {
+        println!(
+            "offered: {:>9.2} committed: {:>9.2} backlog/VN: {:>8.2} -> {:<8.2} p50 age (ms): {:>6} -> {:<6} {}",
+            self.offered_tps,
+            self.committed_tps,
+            self.backlog_mid,
+            self.backlog_end,
+            self.latency_mid,
+            self.latency_end,
+            if self.sustainable {
+                "sustainable"
+            } else {
+                "unsustainable"
+            }
+        );
+    }
+}
+
+fn grows(mid: f64, end: f64, max_growth: f64, slack: f64) -> bool {
+    end > mid * (1.0 + max_growth) && end - mid > slack
+}
+
+/// Runs the scenario in `config` with transactions arriving at `offered_tps`
+pub async fn run_at(config: &SimulationConfig, args: &MaxTpsArgs, offered_tps: f64) -> LoadRun {
+    let mut config = config.clone();
+    config.tx_per_second = Some(offered_tps);
+    config.num_transactions = (offered_tps * config.end_time() as f64 / 1000.0).ceil() as usize;
+    config.print_stats_every = 0;
+    config.until_quiescent = false;
+
+    let result = SimulationBuilder::from_config(config.clone())
+        .build()
+        .run()
+        .await;
+    let metrics = &result.metrics;
+
+    let quarter = result.end_time / 4;
+    let num_vns = config.num_vns.max(1) as f64;
+    let mean_backlog = |from: u128, to: u128| {
+        let samples: Vec<_> = result
+            .backlog
+            .iter()
+            .filter(|s| s.t >= from && s.t < to)
+            .map(|s| s.total() as f64 / num_vns)
+            .collect();
+        samples.iter().sum::<f64>() / samples.len().max(1) as f64
+    };
+    let median_age = |from: u128, to: u128| {
+        Percentiles::from_values(
+            metrics
+                .transactions
+                .values()
+                .filter(|t| t.queued_at >= from && t.queued_at < to)
+                .map(|t| {
+                    t.reached_in_all_shards(TransactionPhase::Committed)
+                        .unwrap_or(result.end_time)
+                        - t.queued_at
+                })
+                .collect(),
+        )
+        .p50
+    };
+
+    let backlog_mid = mean_backlog(quarter, 2 * quarter);
+    let backlog_end = mean_backlog(3 * quarter, result.end_time);
+    let latency_mid = median_age(quarter, 2 * quarter);
+    let latency_end = median_age(2 * quarter, 3 * quarter);
+    let sustainable = !grows(
+        backlog_mid,
+        backlog_end,
+        args.max_growth,
+        config.max_tx_per_step_per_block as f64,
+    ) && !grows(
+        latency_mid as f64,
+        latency_end as f64,
+        args.max_growth,
+        config.time_per_step.as_millis() as f64,
+    );
+    let committed_tps = result.throughput_report().steady_state.committed;
+
+    LoadRun {
+        offered_tps,
+        committed_tps,
+        backlog_mid,
+        backlog_end,
+        latency_mid,
+        latency_end,
+        sustainable,
+    }
+}
+
+/// Increases the offered load until a run is unsustainable, then narrows down on the highest
+/// sustainable load. Fails if the search would never end
+pub async fn search(
+    config: &SimulationConfig,
+    args: &MaxTpsArgs,
+) -> Result<Option<LoadRun>, String> {
+    // Otherwise the offered load never gets past max_tps
+    if args.start_tps <= 0.0 {
+        return Err(format!(
+            "--start-tps must be more than 0, got {}",
+            args.start_tps
+        ));
+    }
+    if args.growth <= 1.0 {
+        return Err(format!("--growth must be more than 1, got {}", args.growth));
+    }
+    let mut best: Option<LoadRun> = None;
+    let mut failed_at = None;
+    let mut offered_tps = args.start_tps;
+    while offered_tps <= args.max_tps {
+        let run = run_at(config, args, offered_tps).await;
+        run.print();
+        if !run.sustainable {
+            failed_at = Some(offered_tps);
+            break;
+        }
+        best = Some(run);
+        offered_tps *= args.growth;
+    }
+
+    if let Some(mut high) = failed_at {
+        let mut low = best.as_ref().map(|b| b.offered_tps).unwrap_or(0.0);
+        for _ in 0..args.refine_steps {
+            let mid = (low + high) / 2.0;
+            let run = run_at(config, args, mid).await;
+            run.print();
+            if run.sustainable {
+                low = mid;
+                best = Some(run);
+            } else {
+                high = mid;
+            }
+        }
+    }
+
+    match &best {
+        Some(best) => println!(
+            "Max sustainable TPS: {:.2} offered, {:.2} committed",
+            best.offered_tps, best.committed_tps
+        ),
+        None => println!(
+            "No sustainable load found, even {:.2} TPS is too much",
+            args.start_tps
+        ),
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// One shard of 4 VNs, which commits at most about 24 tx/s
+    fn config() -> SimulationConfig {
+        SimulationConfig {
+            seed: Some(1),
+            num_vns: 4,
+            num_shards: 1,
+            probability_2_shards: 0,
+            probability_3_shards: 0,
+            max_time: Some(Duration::from_secs(20).into()),
+            ..Default::default()
+        }
+    }
+
+    fn args(max_tps: f64) -> MaxTpsArgs {
+        MaxTpsArgs {
+            start_tps: 10.0,
+            growth: 2.0,
+            max_tps,
+            refine_steps: 3,
+            max_growth: 0.5,
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_tps_if_every_load_is_sustainable() {
+        let best = search(&config(), &args(15.0)).await.unwrap().unwrap();
+        assert_eq!(best.offered_tps, 10.0);
+        assert!(best.sustainable);
+    }
+
+    #[tokio::test]
+    async fn brackets_the_saturation_point() {
+        let config = config();
+        let saturated = run_at(&config, &args(0.0), 200.0).await;
+        assert!(!saturated.sustainable);
+        let capacity = saturated.committed_tps;
+
+        let best = search(&config, &args(10_000.0)).await.unwrap().unwrap();
+        assert!(
+            best.offered_tps > capacity * 0.8 && best.offered_tps < capacity * 1.1,
+            "found {} with a capacity of {}",
+            best.offered_tps,
+            capacity
+        );
+        assert!(
+            !run_at(&config, &args(0.0), capacity * 1.25)
+                .await
+                .sustainable
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_searches_that_never_end() {
+        let mut args = args(100.0);
+        args.growth = 1.0;
+        assert!(search(&config(), &args).await.is_err());
+        args.growth = 2.0;
+        args.start_tps = 0.0;
+        assert!(search(&config(), &args).await.is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::simulation::SimulationResult;
+use crate::transaction::Shard;
+use crate::validator_node::ValidatorNode;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+pub const MB: u64 = 1_000_000;
+
+/// Memory in use by a VN over a window, in bytes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryUsage {
+    pub peak: u64,
+    pub mean: f64,
+}
+
+/// The memory every VN used for execution between `from` and `to`
+#[derive(Debug, Clone)]
+pub struct MemorySample {
+    pub from: u128,
+    pub to: u128,
+    pub vns: BTreeMap<u32, MemoryUsage>,
+}
+
+impl MemorySample {
+    pub fn take(vns: &mut BTreeMap<u32, ValidatorNode>, from: u128, to: u128) -> Self {
+        Self {
+            from,
+            to,
+            vns: vns
+                .iter_mut()
+                .map(|(id, vn)| (*id, vn.memory_usage(from, to)))
+                .collect(),
+        }
+    }
+}
+
+/// How much of its memory budget each shard's VNs needed over the run
+#[derive(Debug, Clone)]
+pub struct ShardMemory {
+    pub shard: Shard,
+    pub peak: u64,
+    pub mean: f64,
+    /// The most memory a VN would have used with no memory limit
+    pub demand: u64,
+    /// Microseconds that transactions waited for memory, summed over the VNs
+    pub memory_stall: u64,
+    pub over_budget: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryReport {
+    /// The memory budget of each VN in bytes
+    pub budget: u64,
+    pub threads: usize,
+    pub samples: Vec<MemorySample>,
+    pub shards: Vec<ShardMemory>,
+}
+
+impl MemoryReport {
+    pub fn from_result(result: &SimulationResult) -> Self {
+        let mut shards: BTreeMap<Shard, ShardMemory> = BTreeMap::new();
+        let run_length: u128 = result.memory.iter().map(|s| s.to - s.from).sum();
+        for vn in result.vns.values() {
+            let stats = result.stats.get(&vn.id).cloned().unwrap_or_default();
+            let mean = result
+                .memory
+                .iter()
+                .filter_map(|s| s.vns.get(&vn.id).map(|u| u.mean * (s.to - s.from) as f64))
+                .sum::<f64>()
+                / run_length.max(1) as f64;
+            let shard = shards.entry(vn.shard).or_insert(ShardMemory {
+                shard: vn.shard,
+                peak: 0,
+                mean: 0.0,
+                demand: 0,
+                memory_stall: 0,
+                over_budget: 0,
+            });
+            shard.peak = shard.peak.max(stats.peak_memory);
+            shard.mean = shard.mean.max(mean);
+            shard.demand = shard.demand.max(stats.memory_demand);
+            shard.memory_stall += stats.memory_stall;
+            shard.over_budget += stats.over_memory_budget;
+        }
+        Self {
+            budget: result.config.vn_memory * MB,
+            threads: result.config.execution_threads,
+            samples: result.memory.clone(),
+            shards: shards.into_values().collect(),
+        }
+    }
+
+    /// The memory a VN should have so that execution never waits for memory: the most any VN
+    /// would have used at once with no limit
+    pub fn recommended(&self) -> u64 {
+        self.shards.iter().map(|s| s.demand).max().unwrap_or(0)
+    }
+
+    pub fn print(&self) {
+        let percent = |bytes: f64| bytes * 100.0 / self.budget.max(1) as f64;
+        println!("==== Memory ====");
+        println!(
+            "Budget per VN: {}MB on {} threads",
+            self.budget / MB,
+            self.threads
+        );
+        println!(
+            "  {:>5} {:>10} {:>8} {:>10} {:>8} {:>12} {:>14} {:>12}",
+            "shard",
+            "peak (MB)",
+            "peak %",
+            "mean (MB)",
+            "mean %",
+            "needed (MB)",
+            "stalled (ms)",
+            "over budget"
+        );
+        for shard in &self.shards {
+            println!(
+                "  {:>5} {:>10} {:>7.1}% {:>10.1} {:>7.1}% {:>12} {:>14} {:>12}",
+                shard.shard.0,
+                shard.peak / MB,
+                percent(shard.peak as f64),
+                shard.mean / MB as f64,
+                percent(shard.mean),
+                shard.demand.div_ceil(MB),
+                shard.memory_stall / 1000,
+                shard.over_budget
+            );
+        }
+
+        let pressure: Vec<_> = self
+            .samples
+            .iter()
+            .map(|s| (s.from, s.vns.values().map(|u| u.peak).max().unwrap_or(0)))
+            .collect();
+        let high = pressure
+            .iter()
+            .filter(|(_, peak)| *peak as f64 >= self.budget as f64 * 0.9)
+            .count();
+        if let Some((t, peak)) = pressure.iter().max_by_key(|(_, peak)| *peak) {
+            println!(
+                "Some VN was above 90% of its budget in {} of {} windows, the highest was {:.1}% at {}ms",
+                high,
+                pressure.len(),
+                percent(*peak as f64),
+                t
+            );
+        }
+        let stalled: u64 = self.shards.iter().map(|s| s.memory_stall).sum();
+        println!(
+            "Memory needed per VN to never wait: {}MB{}",
+            self.recommended().div_ceil(MB),
+            if stalled > 0 {
+                format!(
+                    ", with {}MB transactions waited {}ms for memory",
+                    self.budget / MB,
+                    stalled / 1000
+                )
+            } else {
+                String::new()
+            }
+        );
+    }
+
+    /// Writes the peak and mean memory of every VN in every window as CSV, in MB
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(file, "from,to,vn_id,peak_mb,mean_mb")?;
+        for sample in &self.samples {
+            for (vn_id, usage) in &sample.vns {
+                writeln!(
+                    file,
+                    "{},{},{},{:.3},{:.3}",
+                    sample.from,
+                    sample.to,
+                    vn_id,
+                    usage.peak as f64 / MB as f64,
+                    usage.mean / MB as f64
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+    use crate::simulation::SimulationBuilder;
+    use std::time::Duration;
+
+    async fn run(vn_memory: u64) -> MemoryReport {
+        let config = SimulationConfig {
+            seed: Some(8),
+            num_vns: 4,
+            num_shards: 1,
+            num_transactions: 20,
+            probability_2_shards: 0,
+            probability_3_shards: 0,
+            execution_time: Duration::from_millis(10).into(),
+            execution_threads: 4,
+            tx_memory: 64,
+            vn_memory,
+            until_quiescent: true,
+            max_time: Some(Duration::from_secs(60).into()),
+            ..Default::default()
+        };
+        let result = SimulationBuilder::from_config(config).build().run().await;
+        assert!(result.quiescent);
+        result.memory_report()
+    }
+
+    #[tokio::test]
+    async fn a_budget_breach_is_reported() {
+        let report = run(32).await;
+        assert_eq!(report.budget, 32 * MB);
+        let shard = &report.shards[0];
+        assert!(shard.over_budget > 0);
+        assert!(shard.peak >= 64 * MB);
+        assert!(report.recommended() >= 64 * MB);
+    }
+
+    #[tokio::test]
+    async fn a_tight_budget_makes_transactions_wait() {
+        // Room for two of the four threads
+        let tight = run(128).await;
+        assert_eq!(tight.shards[0].over_budget, 0);
+        assert!(tight.shards[0].peak <= 128 * MB);
+        assert!(tight.shards[0].memory_stall > 0);
+        assert!(tight.recommended() > 128 * MB);
+
+        let roomy = run(1024).await;
+        assert_eq!(roomy.shards[0].memory_stall, 0);
+        assert!(roomy.shards[0].peak > 128 * MB);
+    }
+}
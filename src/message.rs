@@ -1,9 +1,14 @@
 use crate::block::Block;
 use crate::qc::Qc;
 use crate::transaction::Transaction;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::sync::Arc;
 
+/// Bytes in a hash or public key on the wire
+pub const HASH_SIZE: u64 = 32;
+/// Bytes in a signature on the wire
+pub const SIGNATURE_SIZE: u64 = 64;
+
 #[derive(Debug)]
 pub enum Message {
     Transaction {
@@ -37,6 +42,23 @@ pub enum Message {
     },
 }
 
+/// The type of a message, without what it carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MessageKind {
+    Transaction,
+    BlockProposal,
+    NewView,
+    Vote,
+    RequestBlock,
+    RequestBlockResponse,
+}
+
+impl Display for MessageKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
 impl Message {
     pub fn id(&self) -> u32 {
         match self {
@@ -48,6 +70,53 @@ impl Message {
             Message::NewView { id, .. } => *id,
         }
     }
+
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Message::Transaction { .. } => MessageKind::Transaction,
+            Message::BlockProposal { .. } => MessageKind::BlockProposal,
+            Message::NewView { .. } => MessageKind::NewView,
+            Message::Vote { .. } => MessageKind::Vote,
+            Message::RequestBlock { .. } => MessageKind::RequestBlock,
+            Message::RequestBlockResponse { .. } => MessageKind::RequestBlockResponse,
+        }
+    }
+
+    /// Roughly how many bytes the message takes on the wire
+    pub fn size(&self) -> u64 {
+        let header = HASH_SIZE + SIGNATURE_SIZE;
+        header
+            + match self {
+                Message::Transaction { tx, .. } => tx.size(),
+                Message::BlockProposal { block, .. } => block.size(),
+                Message::NewView { high_qc, .. } => 8 + high_qc.size(),
+                Message::Vote { .. } => HASH_SIZE + 8,
+                Message::RequestBlock { .. } => HASH_SIZE,
+                Message::RequestBlockResponse { block, .. } => block.size(),
+            }
+    }
+
+    /// The part of `size` taken up by transactions
+    pub fn transaction_bytes(&self) -> u64 {
+        match self {
+            Message::Transaction { tx, .. } => tx.size(),
+            Message::BlockProposal { block, .. } => block.transaction_bytes(),
+            Message::RequestBlockResponse { block, .. } => block.transaction_bytes(),
+            Message::NewView { .. } | Message::Vote { .. } | Message::RequestBlock { .. } => 0,
+        }
+    }
+
+    /// How many signatures the receiver has to check, including the sender's
+    pub fn signatures(&self) -> u64 {
+        1 + match self {
+            Message::Transaction { .. } => 1,
+            Message::BlockProposal { block, .. } => block.justify.votes.len() as u64,
+            Message::NewView { high_qc, .. } => high_qc.votes.len() as u64,
+            Message::Vote { .. } => 0,
+            Message::RequestBlock { .. } => 0,
+            Message::RequestBlockResponse { block, .. } => block.justify.votes.len() as u64,
+        }
+    }
 }
 
 impl Display for Message {
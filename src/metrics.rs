@@ -0,0 +1,586 @@
+use crate::block::Block;
+use crate::message::{Message, MessageKind};
+use crate::observer::SimulationObserver;
+use crate::qc::Qc;
+use crate::substate::SubstateAddress;
+use crate::transaction::{AbortReason, Shard, Transaction};
+use crate::validator_node::Mempool;
+use async_trait::async_trait;
+use log::warn;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TransactionPhase {
+    PreparedWaiting,
+    PreparedReady,
+    PrecommitWaiting,
+    PrecommitReady,
+    Committed,
+    Aborted,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockRecord {
+    pub id: u32,
+    pub parent_id: u32,
+    pub shard: Shard,
+    pub height: u32,
+    pub proposed_by: u32,
+    pub justify_qc_id: u32,
+    pub prepare_txs: Vec<u32>,
+    pub precommit_txs: Vec<u32>,
+    pub commit_txs: Vec<u32>,
+    pub abort_txs: Vec<u32>,
+    pub created_at: u128,
+}
+
+#[derive(Clone, Debug)]
+pub struct QcRecord {
+    pub id: u32,
+    pub created_by: u32,
+    pub block_id: u32,
+    pub created_at: u128,
+}
+
+#[derive(Clone, Debug)]
+pub struct VoteRecord {
+    pub vn_id: u32,
+    pub block_id: u32,
+    pub t: u128,
+}
+
+/// A transaction that a leader aborted because another transaction held one of its inputs
+#[derive(Clone, Debug)]
+pub struct LockConflictRecord {
+    pub tx_id: u32,
+    pub vn_id: u32,
+    pub shard: Shard,
+    pub address: SubstateAddress,
+    pub held_by: u32,
+    pub t: u128,
+}
+
+/// A transaction that waited too long for another shard, and whether it was aborted or retried
+#[derive(Clone, Debug)]
+pub struct TimeoutRecord {
+    pub tx_id: u32,
+    pub vn_id: u32,
+    pub shard: Shard,
+    pub mempool: Mempool,
+    pub aborted: bool,
+    pub t: u128,
+}
+
+#[derive(Clone, Debug)]
+pub struct MessageRecord {
+    pub id: u32,
+    pub from: u32,
+    pub to: u32,
+    pub kind: MessageKind,
+    pub t: u128,
+}
+
+#[derive(Clone, Debug)]
+pub struct TransactionPhaseRecord {
+    pub phase: TransactionPhase,
+    pub vn_id: u32,
+    pub shard: Shard,
+    pub qc_id: u32,
+    pub t: u128,
+}
+
+#[derive(Clone, Debug)]
+pub struct TransactionRecord {
+    pub tx: Transaction,
+    pub queued_at: u128,
+    pub phases: Vec<TransactionPhaseRecord>,
+    /// Why it was aborted, if any shard aborted it
+    pub abort_reason: Option<AbortReason>,
+}
+
+impl TransactionRecord {
+    /// The first time that `phase` was reached in `shard`
+    pub fn first_reached(&self, phase: TransactionPhase, shard: Shard) -> Option<u128> {
+        self.phases
+            .iter()
+            .filter(|p| p.phase == phase && p.shard == shard)
+            .map(|p| p.t)
+            .min()
+    }
+
+    /// The time at which `phase` was reached in every involved shard
+    pub fn reached_in_all_shards(&self, phase: TransactionPhase) -> Option<u128> {
+        self.tx
+            .shards
+            .iter()
+            .map(|s| self.first_reached(phase, *s))
+            .collect::<Option<Vec<_>>>()
+            .and_then(|times| times.into_iter().max())
+    }
+}
+
+/// Everything that was observed during a simulation
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    pub shards: Vec<u32>,
+    pub vns: BTreeMap<u32, (Shard, u128)>,
+    pub indexers: Vec<u32>,
+    pub blocks: HashMap<u32, BlockRecord>,
+    pub qcs: HashMap<u32, QcRecord>,
+    pub votes: Vec<VoteRecord>,
+    pub transactions: BTreeMap<u32, TransactionRecord>,
+    pub messages: Vec<MessageRecord>,
+    pub lock_conflicts: Vec<LockConflictRecord>,
+    pub timeouts: Vec<TimeoutRecord>,
+    pub request_blocks: HashMap<u32, usize>,
+    pub leader_failures: HashMap<u32, usize>,
+    pub ended_at: u128,
+}
+
+impl Metrics {
+    pub fn committed_transactions(&self) -> Vec<&TransactionRecord> {
+        self.transactions
+            .values()
+            .filter(|t| {
+                t.reached_in_all_shards(TransactionPhase::Committed)
+                    .is_some()
+            })
+            .collect()
+    }
+
+    pub fn aborted_transactions(&self) -> Vec<&TransactionRecord> {
+        self.transactions
+            .values()
+            .filter(|t| t.reached_in_all_shards(TransactionPhase::Aborted).is_some())
+            .collect()
+    }
+
+    pub fn messages_by_kind(&self) -> BTreeMap<MessageKind, usize> {
+        let mut res = BTreeMap::new();
+        for m in &self.messages {
+            *res.entry(m.kind).or_insert(0) += 1;
+        }
+        res
+    }
+
+    pub fn print_summary(&self) {
+        let mut reached: BTreeMap<TransactionPhase, HashSet<u32>> = BTreeMap::new();
+        for tx in self.transactions.values() {
+            for phase in &tx.phases {
+                reached.entry(phase.phase).or_default().insert(tx.tx.id);
+            }
+        }
+        println!("==== Summary ====");
+        println!(
+            "VNs: {} shards: {} blocks: {} qcs: {} votes: {} messages: {}",
+            self.vns.len(),
+            self.shards.len(),
+            self.blocks.len(),
+            self.qcs.len(),
+            self.votes.len(),
+            self.messages.len()
+        );
+        for (kind, count) in self.messages_by_kind() {
+            println!("  {}: {}", kind, count);
+        }
+        println!("Transactions queued: {}", self.transactions.len());
+        for (phase, txs) in &reached {
+            println!("  {:?} (in any shard): {}", phase, txs.len());
+        }
+        println!(
+            "  Committed in all shards: {}",
+            self.committed_transactions().len()
+        );
+        let mut aborted: BTreeMap<AbortReason, usize> = BTreeMap::new();
+        for tx in self.aborted_transactions() {
+            if let Some(reason) = tx.abort_reason {
+                *aborted.entry(reason).or_insert(0) += 1;
+            }
+        }
+        println!(
+            "  Aborted in all shards: {}",
+            aborted.values().sum::<usize>()
+        );
+        for (reason, count) in &aborted {
+            println!("    {}: {}", reason, count);
+        }
+        println!(
+            "Block requests: {} leader failures: {}",
+            self.request_blocks.values().sum::<usize>(),
+            self.leader_failures.values().sum::<usize>()
+        );
+    }
+
+    fn record_phase(
+        &mut self,
+        tx_id: u32,
+        phase: TransactionPhase,
+        vn_id: u32,
+        shard: Shard,
+        qc_id: u32,
+        t: u128,
+    ) {
+        if let Some(record) = self.transactions.get_mut(&tx_id) {
+            record.phases.push(TransactionPhaseRecord {
+                phase,
+                vn_id,
+                shard,
+                qc_id,
+                t,
+            });
+        } else {
+            warn!("Transaction {} was not queued", tx_id);
+        }
+    }
+}
+
+/// Keeps every event in memory so that it can be summarised or inspected after the run.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsRecorder {
+    inner: Arc<RwLock<Metrics>>,
+}
+
+impl MetricsRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn read(&self) -> RwLockReadGuard<'_, Metrics> {
+        self.inner.read().await
+    }
+
+    /// Moves the recorded metrics out, leaving the recorder empty
+    pub async fn take(&self) -> Metrics {
+        std::mem::take(&mut *self.inner.write().await)
+    }
+}
+
+#[async_trait]
+impl SimulationObserver for MetricsRecorder {
+    async fn create_shard(&self, shard_id: u32) {
+        self.inner.write().await.shards.push(shard_id);
+    }
+
+    async fn create_vn(&self, vn_id: u32, shard_id: Shard, latency: u128) {
+        self.inner
+            .write()
+            .await
+            .vns
+            .insert(vn_id, (shard_id, latency));
+    }
+
+    async fn create_indexer(&self, id: u32) {
+        self.inner.write().await.indexers.push(id);
+    }
+
+    async fn on_create_leaf(&self, block: Arc<Block>, time: u128) {
+        let ids = |txs: &Vec<Arc<Transaction>>| txs.iter().map(|t| t.id).collect();
+        self.inner.write().await.blocks.insert(
+            block.id,
+            BlockRecord {
+                id: block.id,
+                parent_id: block.parent_id,
+                shard: block.shard,
+                height: block.height,
+                proposed_by: block.proposed_by,
+                justify_qc_id: block.justify.id,
+                prepare_txs: ids(&block.prepare_txs),
+                precommit_txs: ids(&block.precommit_txs),
+                commit_txs: ids(&block.commit_txs),
+                abort_txs: block.abort_txs.iter().map(|(t, _)| t.id).collect(),
+                created_at: time,
+            },
+        );
+    }
+
+    async fn on_vote(&self, vn_id: u32, block_id: u32, t: u128) {
+        self.inner
+            .write()
+            .await
+            .votes
+            .push(VoteRecord { vn_id, block_id, t });
+    }
+
+    async fn on_qc_created(&self, qc_id: u32, vn_id: u32, t: u128, block_id: u32) {
+        self.inner.write().await.qcs.insert(
+            qc_id,
+            QcRecord {
+                id: qc_id,
+                created_by: vn_id,
+                block_id,
+                created_at: t,
+            },
+        );
+    }
+
+    async fn on_transaction_queued(&self, tx_id: u32, t: u128, tx: &Transaction) {
+        self.inner.write().await.transactions.insert(
+            tx_id,
+            TransactionRecord {
+                tx: tx.clone(),
+                queued_at: t,
+                phases: vec![],
+                abort_reason: None,
+            },
+        );
+    }
+
+    async fn on_transaction_moved_to_prepare_ready(
+        &self,
+        tx_id: u32,
+        in_vn: u32,
+        t: u128,
+        in_block: u32,
+    ) {
+        let mut metrics = self.inner.write().await;
+        let Some((shard, _)) = metrics.vns.get(&in_vn).copied() else {
+            warn!("VN {} was not created", in_vn);
+            return;
+        };
+        // The foreign block that completed the shards has no QC of its own yet, so the QC it
+        // carries stands in
+        let qc_id = metrics
+            .blocks
+            .get(&in_block)
+            .map(|b| b.justify_qc_id)
+            .unwrap_or_default();
+        metrics.record_phase(
+            tx_id,
+            TransactionPhase::PreparedReady,
+            in_vn,
+            shard,
+            qc_id,
+            t,
+        );
+    }
+
+    async fn on_transaction_prepared_ready(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.inner.write().await.record_phase(
+            tx_id,
+            TransactionPhase::PreparedReady,
+            vn_id,
+            shard,
+            in_qc.id,
+            t,
+        );
+    }
+
+    async fn on_transaction_prepared_waiting(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.inner.write().await.record_phase(
+            tx_id,
+            TransactionPhase::PreparedWaiting,
+            vn_id,
+            shard,
+            in_qc.id,
+            t,
+        );
+    }
+
+    async fn on_transaction_precommit_ready(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.inner.write().await.record_phase(
+            tx_id,
+            TransactionPhase::PrecommitReady,
+            vn_id,
+            shard,
+            in_qc.id,
+            t,
+        );
+    }
+
+    async fn on_transaction_precommit_waiting(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.inner.write().await.record_phase(
+            tx_id,
+            TransactionPhase::PrecommitWaiting,
+            vn_id,
+            shard,
+            in_qc.id,
+            t,
+        );
+    }
+
+    async fn on_transaction_committed(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.inner.write().await.record_phase(
+            tx_id,
+            TransactionPhase::Committed,
+            vn_id,
+            shard,
+            in_qc.id,
+            t,
+        );
+    }
+
+    async fn on_transaction_aborted(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        reason: AbortReason,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        let mut metrics = self.inner.write().await;
+        if let Some(record) = metrics.transactions.get_mut(&tx_id) {
+            record.abort_reason.get_or_insert(reason);
+        }
+        metrics.record_phase(tx_id, TransactionPhase::Aborted, vn_id, shard, in_qc.id, t);
+    }
+
+    async fn on_transaction_timed_out(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        mempool: Mempool,
+        aborted: bool,
+        t: u128,
+    ) {
+        self.inner.write().await.timeouts.push(TimeoutRecord {
+            tx_id,
+            vn_id,
+            shard,
+            mempool,
+            aborted,
+            t,
+        });
+    }
+
+    async fn on_lock_conflict(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        address: SubstateAddress,
+        held_by: u32,
+        t: u128,
+    ) {
+        self.inner
+            .write()
+            .await
+            .lock_conflicts
+            .push(LockConflictRecord {
+                tx_id,
+                vn_id,
+                shard,
+                address,
+                held_by,
+                t,
+            });
+    }
+
+    async fn on_message_sent(&self, from: u32, to: u32, message: &Message, t: u128) {
+        self.inner.write().await.messages.push(MessageRecord {
+            id: message.id(),
+            from,
+            to,
+            kind: message.kind(),
+            t,
+        });
+    }
+
+    async fn on_request_block(&self, id: u32) {
+        *self
+            .inner
+            .write()
+            .await
+            .request_blocks
+            .entry(id)
+            .or_insert(0) += 1;
+    }
+
+    async fn on_simulation_end(&self, t: u128) {
+        self.inner.write().await.ended_at = t;
+    }
+
+    async fn on_leader_failure(&self, id: u32) {
+        *self
+            .inner
+            .write()
+            .await
+            .leader_failures
+            .entry(id)
+            .or_insert(0) += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Simulation;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn records_commits_and_messages() {
+        let result = Simulation::builder()
+            .seed(5)
+            .num_vns(8)
+            .num_shards(2)
+            .workload(20, None)
+            .cross_shard_probabilities([50, 0, 0, 0])
+            .until_quiescent(true)
+            .max_time(Duration::from_secs(60))
+            .build()
+            .run()
+            .await;
+        let metrics = &result.metrics;
+        assert_eq!(metrics.vns.len(), 8);
+        assert_eq!(metrics.transactions.len(), 20);
+        assert_eq!(metrics.committed_transactions().len(), 20);
+        for tx in metrics.committed_transactions() {
+            for shard in &tx.tx.shards {
+                assert!(tx
+                    .first_reached(TransactionPhase::Committed, *shard)
+                    .is_some());
+            }
+        }
+        assert!(metrics
+            .transactions
+            .values()
+            .any(|t| t.tx.shards.len() == 2));
+
+        let by_kind = metrics.messages_by_kind();
+        assert_eq!(by_kind.values().sum::<usize>(), metrics.messages.len());
+        // The indexer sends every transaction to every VN
+        assert_eq!(by_kind[&MessageKind::Transaction], 20 * 8);
+        // Every vote is sent to the next leader, but a late one is not counted
+        assert!(by_kind[&MessageKind::Vote] >= metrics.votes.len());
+        assert!(!metrics.votes.is_empty());
+        assert!(by_kind[&MessageKind::BlockProposal] >= metrics.blocks.len());
+    }
+}
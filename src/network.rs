@@ -1,77 +1,61 @@
 use crate::message::Message;
 use crate::network_connection::NetworkConnection;
-use crate::node_id::NodeId;
+use crate::sim_rng::SimRng;
 use crate::subscriber::Subscriber;
-use rand::Rng;
-use std::collections::HashMap;
-use std::time::Duration;
+use log::debug;
+use std::collections::BTreeMap;
 
 #[derive(Debug)]
 pub struct Network {
-    connections: HashMap<u32, HashMap<u32, NetworkConnection>>,
+    connections: BTreeMap<u32, BTreeMap<u32, NetworkConnection>>,
     subscriber: Subscriber,
+    rng: SimRng,
 }
 
 impl Network {
-    pub fn new(subscriber: Subscriber) -> Self {
+    pub fn new(subscriber: Subscriber, rng: SimRng) -> Self {
         Self {
-            connections: HashMap::new(),
+            connections: BTreeMap::new(),
             subscriber,
+            rng,
         }
     }
 
     pub fn add_connection(&mut self, from: u32, to: u32, min_latency: u128, max_latency: u128) {
         let latency = if min_latency != max_latency {
-            rand::thread_rng()
-                .gen_range(min_latency..max_latency)
-                .try_into()
-                .unwrap()
+            self.rng.gen_range(min_latency..max_latency)
         } else {
             min_latency
         };
 
         self.connections
             .entry(from)
-            .or_insert_with(HashMap::new)
+            .or_default()
             .insert(to, NetworkConnection::new(latency));
         self.connections
             .entry(to)
-            .or_insert_with(HashMap::new)
+            .or_default()
             .insert(from, NetworkConnection::new(latency));
     }
 
-    pub async fn send_message(&mut self, from: u32, to: u32, message: Message, current_time: u128) {
-        let mut connection = self
-            .connections
-            .get_mut(&from)
-            .unwrap()
-            .get_mut(&to)
-            .unwrap();
+    /// Sends `message` and returns the time it will arrive at `to`
+    pub async fn send_message(
+        &mut self,
+        from: u32,
+        to: u32,
+        message: &Message,
+        current_time: u128,
+    ) -> u128 {
+        let connection = self.connections.get(&from).unwrap().get(&to).unwrap();
         self.subscriber
-            .on_message_sent(from, to, message.id(), message.to_string(), current_time)
+            .on_message_sent(from, to, message, current_time)
             .await;
-        println!("{} Sent -> {}: {}", from, to, message.to_string());
-        connection.push_message(message, current_time);
-    }
-
-    pub fn update(&mut self, current_time: u128) -> Vec<(u32, Message)> {
-        let mut messages = Vec::new();
-        for (from, connections) in &mut self.connections {
-            for (to, connection) in connections {
-                loop {
-                    if let Some((time, message)) = connection.peek() {
-                        if *time <= current_time {
-                            let (_, message) = connection.messages.pop_front().unwrap();
-                            messages.push((*to, message));
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-                }
-            }
+        if from != to {
+            self.subscriber
+                .on_bytes_sent(from, to, message.size(), message.transaction_bytes())
+                .await;
         }
-        messages
+        debug!("{} Sent -> {}: {}", from, to, message);
+        connection.arrival_time(current_time)
     }
 }
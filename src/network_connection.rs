@@ -1,26 +1,15 @@
-use crate::message::Message;
-use std::collections::VecDeque;
-use std::time::Duration;
-
 #[derive(Debug)]
 pub struct NetworkConnection {
     pub latency: u128,
-    pub messages: VecDeque<(u128, Message)>,
 }
 
 impl NetworkConnection {
     pub fn new(latency: u128) -> Self {
-        Self {
-            latency,
-            messages: VecDeque::new(),
-        }
-    }
-
-    pub fn push_message(&mut self, message: Message, at_time: u128) {
-        self.messages.push_back((at_time + self.latency, message));
+        Self { latency }
     }
 
-    pub fn peek(&self) -> Option<&(u128, Message)> {
-        self.messages.front()
+    /// When a message sent at `at_time` arrives at the other end
+    pub fn arrival_time(&self, at_time: u128) -> u128 {
+        at_time + self.latency
     }
 }
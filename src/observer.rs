@@ -0,0 +1,161 @@
+use crate::block::Block;
+use crate::message::Message;
+use crate::qc::Qc;
+use crate::substate::SubstateAddress;
+use crate::transaction::{AbortReason, Shard, Transaction};
+use crate::validator_node::Mempool;
+use async_trait::async_trait;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// A sink for the events produced while a simulation runs.
+///
+/// Every hook has an empty default so that an observer only needs to implement the events it
+/// cares about. Observers are composed by the `Subscriber`, which forwards each event to all of
+/// them in order.
+#[async_trait]
+pub trait SimulationObserver: Debug + Send + Sync {
+    async fn create_shard(&self, _shard_id: u32) {}
+
+    async fn create_vn(&self, _vn_id: u32, _shard_id: Shard, _latency: u128) {}
+
+    async fn create_indexer(&self, _id: u32) {}
+
+    async fn on_create_leaf(&self, _block: Arc<Block>, _time: u128) {}
+
+    async fn on_vote(&self, _vn_id: u32, _block_id: u32, _t: u128) {}
+
+    async fn on_qc_created(&self, _qc_id: u32, _vn_id: u32, _t: u128, _block_id: u32) {}
+
+    /// `vn_id` committed `block` at the end of a 3 chain. Blocks are committed in height order
+    async fn on_block_committed(&self, _vn_id: u32, _block: Arc<Block>, _t: u128) {}
+
+    async fn on_transaction_queued(&self, _tx_id: u32, _t: u128, _tx: &Transaction) {}
+
+    async fn on_transaction_moved_to_prepare_ready(
+        &self,
+        _tx_id: u32,
+        _in_vn: u32,
+        _t: u128,
+        _in_block: u32,
+    ) {
+    }
+
+    async fn on_transaction_prepared_ready(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _in_qc: Arc<Qc>,
+        _t: u128,
+    ) {
+    }
+
+    async fn on_transaction_prepared_waiting(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _in_qc: Arc<Qc>,
+        _t: u128,
+    ) {
+    }
+
+    async fn on_transaction_precommit_ready(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _in_qc: Arc<Qc>,
+        _t: u128,
+    ) {
+    }
+
+    async fn on_transaction_precommit_waiting(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _in_qc: Arc<Qc>,
+        _t: u128,
+    ) {
+    }
+
+    async fn on_transaction_committed(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _in_qc: Arc<Qc>,
+        _t: u128,
+    ) {
+    }
+
+    /// `vn_id` learned from `in_block` that a shard voted to abort `tx_id`. The transaction does
+    /// not wait for the other shards any more, and goes on to be committed as aborted
+    async fn on_transaction_abort_vote(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _reason: AbortReason,
+        _in_block: u32,
+        _t: u128,
+    ) {
+    }
+
+    /// `vn_id` applied the commit of an aborted transaction. Its locks are released and it will
+    /// not be prepared again
+    async fn on_transaction_aborted(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _reason: AbortReason,
+        _in_qc: Arc<Qc>,
+        _t: u128,
+    ) {
+    }
+
+    /// The leader `vn_id` found that `tx_id` had waited in `mempool` for longer than the phase's
+    /// timeout. It either precommitted it as aborted or sent its block again to the shards it waits on
+    async fn on_transaction_timed_out(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _mempool: Mempool,
+        _aborted: bool,
+        _t: u128,
+    ) {
+    }
+
+    /// The leader `vn_id` left `tx_id` out of its block because `held_by` has `address` locked,
+    /// so the transaction is aborted in every shard
+    async fn on_lock_conflict(
+        &self,
+        _tx_id: u32,
+        _vn_id: u32,
+        _shard: Shard,
+        _address: SubstateAddress,
+        _held_by: u32,
+        _t: u128,
+    ) {
+    }
+
+    async fn on_message_sent(&self, _from: u32, _to: u32, _message: &Message, _t: u128) {}
+
+    async fn on_message_delivered(&self, _to: u32, _message: &Message, _t: u128) {}
+
+    async fn on_request_block(&self, _id: u32) {}
+
+    async fn on_leader_failure(&self, _id: u32) {}
+
+    /// A VN stopped handling messages because of a scheduled fault
+    async fn on_vn_crashed(&self, _vn_id: u32, _t: u128) {}
+
+    async fn on_vn_recovered(&self, _vn_id: u32, _t: u128) {}
+
+    /// Called once after the last step of the simulation
+    async fn on_simulation_end(&self, _t: u128) {}
+}
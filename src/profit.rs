@@ -0,0 +1,268 @@
+use crate::config::SimulationConfig;
+use crate::memory_report::MB;
+use crate::simulation::SimulationResult;
+use crate::subscriber::Stats;
+use crate::transaction::Shard;
+
+/// What one VN earned and spent over a run, all in the same unit as fees
+#[derive(Debug, Clone)]
+pub struct VnProfit {
+    pub vn_id: u32,
+    pub shard: Shard,
+    pub rewards: f64,
+    pub bandwidth_cost: f64,
+    pub cpu_cost: f64,
+    pub storage_cost: f64,
+    /// The part of the costs that comes from sending, receiving, executing and storing
+    /// transactions, which grows with the load. The rest is paid for block headers, QCs and votes
+    /// whatever the load
+    pub transaction_cost: f64,
+    /// Seconds of CPU spent on signature checks and execution
+    pub cpu_time: f64,
+    /// Bytes of blocks held at the end of the run
+    pub storage_bytes: u64,
+}
+
+impl VnProfit {
+    pub fn from_stats(
+        vn_id: u32,
+        shard: Shard,
+        stats: &Stats,
+        storage_bytes: u64,
+        stored_transaction_bytes: u64,
+        config: &SimulationConfig,
+    ) -> Self {
+        let execution_time = stats.execution_time as f64 / 1_000_000.0;
+        let cpu_time = stats.signatures_checked as f64 * config.signature_check_time.as_secs_f64()
+            + execution_time;
+        let mb = MB as f64;
+        Self {
+            vn_id,
+            shard,
+            rewards: stats.rewards,
+            bandwidth_cost: (stats.bytes_sent + stats.bytes_received) as f64 / mb
+                * config.cost_per_mb,
+            cpu_cost: cpu_time * config.cost_per_cpu_second,
+            storage_cost: storage_bytes as f64 / mb * config.cost_per_mb_stored,
+            transaction_cost: stats.transaction_bytes as f64 / mb * config.cost_per_mb
+                + execution_time * config.cost_per_cpu_second
+                + stored_transaction_bytes as f64 / mb * config.cost_per_mb_stored,
+            cpu_time,
+            storage_bytes,
+        }
+    }
+
+    pub fn costs(&self) -> f64 {
+        self.bandwidth_cost + self.cpu_cost + self.storage_cost
+    }
+
+    pub fn fixed_cost(&self) -> f64 {
+        self.costs() - self.transaction_cost
+    }
+
+    pub fn net(&self) -> f64 {
+        self.rewards - self.costs()
+    }
+
+    /// Net profit as a fraction of rewards. A VN that earned nothing has no margin
+    pub fn margin(&self) -> Option<f64> {
+        (self.rewards > 0.0).then(|| self.net() / self.rewards)
+    }
+
+    pub fn at_loss(&self) -> bool {
+        self.net() < 0.0
+    }
+
+    /// The load at which this VN would break even, given it ran at `tps`. Rewards and
+    /// transaction costs are taken to grow in step with the load while the fixed costs stay the
+    /// same. `None` if every transaction costs the VN more than it earns, so no load pays
+    pub fn break_even_tps(&self, tps: f64) -> Option<f64> {
+        let earned = self.rewards - self.transaction_cost;
+        (earned > 0.0).then(|| tps * self.fixed_cost() / earned)
+    }
+}
+
+/// The rewards against operating costs of every VN in a run
+#[derive(Debug, Clone)]
+pub struct ProfitReport {
+    pub offered_tps: Option<f64>,
+    pub committed_tps: f64,
+    pub vns: Vec<VnProfit>,
+}
+
+impl ProfitReport {
+    pub fn from_result(result: &SimulationResult) -> Self {
+        let vns = result
+            .vns
+            .values()
+            .map(|vn| {
+                let storage_bytes = vn.blocks.values().map(|b| b.size()).sum();
+                let stored_transaction_bytes =
+                    vn.blocks.values().map(|b| b.transaction_bytes()).sum();
+                VnProfit::from_stats(
+                    vn.id,
+                    vn.shard,
+                    &result.stats.get(&vn.id).cloned().unwrap_or_default(),
+                    storage_bytes,
+                    stored_transaction_bytes,
+                    &result.config,
+                )
+            })
+            .collect();
+        Self {
+            offered_tps: result.config.tx_per_second,
+            committed_tps: result.throughput_report().steady_state.committed,
+            vns,
+        }
+    }
+
+    pub fn at_loss(&self) -> usize {
+        self.vns.iter().filter(|v| v.at_loss()).count()
+    }
+
+    pub fn mean_net(&self) -> f64 {
+        self.vns.iter().map(|v| v.net()).sum::<f64>() / self.vns.len().max(1) as f64
+    }
+
+    /// The committed load at which every VN breaks even, or `None` if some VN never does. Below
+    /// the most the network can commit, this is also the offered load
+    pub fn break_even_tps(&self) -> Option<f64> {
+        self.vns
+            .iter()
+            .map(|v| v.break_even_tps(self.committed_tps))
+            .try_fold(0.0, |max: f64, tps| tps.map(|tps| max.max(tps)))
+    }
+
+    pub fn print(&self) {
+        println!("==== Profit per VN ====");
+        println!(
+            "  {:>5} {:>5} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8} {:>10}",
+            "VN",
+            "shard",
+            "rewards",
+            "bandwidth",
+            "cpu",
+            "storage",
+            "per tx",
+            "net",
+            "margin",
+            "break-even"
+        );
+        for vn in &self.vns {
+            println!(
+                "  {:>5} {:>5} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>8} {:>10}{}",
+                vn.vn_id,
+                vn.shard.0,
+                vn.rewards,
+                vn.bandwidth_cost,
+                vn.cpu_cost,
+                vn.storage_cost,
+                vn.transaction_cost,
+                vn.net(),
+                vn.margin()
+                    .map(|m| format!("{:.1}%", m * 100.0))
+                    .unwrap_or_else(|| "-".to_string()),
+                vn.break_even_tps(self.committed_tps)
+                    .map(|tps| format!("{:.2}", tps))
+                    .unwrap_or_else(|| "never".to_string()),
+                if vn.at_loss() { " LOSS" } else { "" }
+            );
+        }
+        let load = match self.offered_tps {
+            Some(tps) => format!("{:.2} tx/s offered", tps),
+            None => "all transactions queued at the start".to_string(),
+        };
+        println!(
+            "{} of {} VNs run at a loss with {} ({:.2} tx/s committed), mean net: {:.2}",
+            self.at_loss(),
+            self.vns.len(),
+            load,
+            self.committed_tps,
+            self.mean_net()
+        );
+        match self.break_even_tps() {
+            Some(tps) => println!(
+                "Every VN breaks even at {:.2} tx/s committed or more, with the same fee per transaction",
+                tps
+            ),
+            None => println!("Some VNs spend more on each transaction than they earn from it, so no load pays"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn assert_near(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    /// A VN that moved 2 MB, 1 MB of it transactions, checked signatures for 1s, executed for 2s
+    /// and stores 2 MB of blocks, 1 MB of it transactions
+    fn vn(rewards: f64) -> VnProfit {
+        let config = SimulationConfig {
+            signature_check_time: Duration::from_micros(100).into(),
+            cost_per_mb: 10.0,
+            cost_per_cpu_second: 50.0,
+            cost_per_mb_stored: 20.0,
+            ..Default::default()
+        };
+        let stats = Stats {
+            rewards,
+            bytes_sent: MB,
+            bytes_received: MB,
+            transaction_bytes: MB,
+            signatures_checked: 10_000,
+            execution_time: 2_000_000,
+            ..Default::default()
+        };
+        VnProfit::from_stats(1, Shard(0), &stats, 2 * MB, MB, &config)
+    }
+
+    #[test]
+    fn costs_are_priced_per_mb_and_cpu_second() {
+        let vn = vn(300.0);
+        assert_near(vn.bandwidth_cost, 20.0);
+        assert_near(vn.cpu_time, 3.0);
+        assert_near(vn.cpu_cost, 150.0);
+        assert_near(vn.storage_cost, 40.0);
+        // 1 MB sent or received, 2s executing and 1 MB stored
+        assert_near(vn.transaction_cost, 10.0 + 100.0 + 20.0);
+        assert_near(vn.costs(), 210.0);
+        assert_near(vn.fixed_cost(), 80.0);
+        assert_near(vn.net(), 90.0);
+        assert_near(vn.margin().unwrap(), 0.3);
+        assert!(!vn.at_loss());
+    }
+
+    #[test]
+    fn break_even_covers_the_fixed_costs() {
+        // At 10 tx/s the VN earns 170 over its transaction costs, against 80 of fixed costs
+        let tps = vn(300.0).break_even_tps(10.0).unwrap();
+        assert_near(tps, 10.0 * 80.0 / 170.0);
+
+        let losing = vn(100.0);
+        assert!(losing.at_loss());
+        assert_near(losing.margin().unwrap(), -1.1);
+        assert_eq!(losing.break_even_tps(10.0), None);
+        assert_eq!(vn(0.0).margin(), None);
+    }
+
+    #[test]
+    fn the_report_breaks_even_when_every_vn_does() {
+        let mut report = ProfitReport {
+            offered_tps: Some(10.0),
+            committed_tps: 10.0,
+            vns: vec![vn(300.0), vn(500.0)],
+        };
+        assert_near(report.break_even_tps().unwrap(), 10.0 * 80.0 / 170.0);
+        assert_eq!(report.at_loss(), 0);
+        assert_near(report.mean_net(), (90.0 + 290.0) / 2.0);
+
+        report.vns.push(vn(100.0));
+        assert_eq!(report.break_even_tps(), None);
+        assert_eq!(report.at_loss(), 1);
+    }
+}
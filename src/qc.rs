@@ -1,5 +1,4 @@
-use crate::block::Block;
-use std::sync::Arc;
+use crate::message::{HASH_SIZE, SIGNATURE_SIZE};
 
 #[derive(Debug)]
 pub struct Qc {
@@ -18,6 +17,11 @@ impl Qc {
             votes,
         }
     }
+    /// Bytes on the wire and in storage: the block hash, its height and a signature per vote
+    pub fn size(&self) -> u64 {
+        HASH_SIZE + 8 + SIGNATURE_SIZE * self.votes.len() as u64
+    }
+
     pub fn genesis() -> Self {
         Self {
             id: 0,
@@ -0,0 +1,191 @@
+use crate::committee_safety::CommitteeSafety;
+use crate::config::SimulationConfig;
+use crate::run_summary::{spawn_runs, RunSummary};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// The seeds for `n` replications of a run. The first is `seed` itself so that a single
+/// replication is the same as a plain run, the rest are drawn from it.
+pub fn derive_seeds(seed: u64, n: usize) -> Vec<u64> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut seeds = vec![seed];
+    seeds.extend((1..n).map(|_| rng.gen::<u64>()));
+    seeds
+}
+
+/// Two sided 95% critical values of Student's t distribution for 1 to 30 degrees of freedom
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// The mean of a sample and the half width of its 95% confidence interval
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfidenceInterval {
+    pub mean: f64,
+    pub half_width: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ConfidenceInterval {
+    pub fn from_values(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let half_width = if values.len() < 2 {
+            0.0
+        } else {
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            let t = T_95.get(values.len() - 2).cloned().unwrap_or(1.96);
+            t * (variance / n).sqrt()
+        };
+        Self {
+            mean,
+            half_width,
+            min: values.iter().cloned().fold(f64::INFINITY, f64::min),
+            max: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// The same scenario run under several seeds
+#[derive(Debug, Clone)]
+pub struct Replications {
+    pub runs: Vec<RunSummary>,
+}
+
+impl Replications {
+    /// Runs `config` once for each seed from `derive_seeds`, `jobs` at a time
+    pub async fn run(config: &SimulationConfig, replications: usize, jobs: usize) -> Self {
+        let safety = CommitteeSafety::from_config(config);
+        let configs = derive_seeds(config.seed.unwrap_or_default(), replications)
+            .into_iter()
+            .map(|seed| {
+                let mut config = config.clone();
+                config.seed = Some(seed);
+                (config, safety.clone())
+            })
+            .collect();
+        let mut runs = vec![];
+        for handle in spawn_runs(configs, jobs) {
+            runs.push(handle.await.expect("replication panicked"));
+        }
+        Self { runs }
+    }
+
+    /// The confidence interval of every metric in `RunSummary::values`
+    pub fn intervals(&self) -> Vec<(&'static str, ConfidenceInterval)> {
+        let Some(first) = self.runs.first() else {
+            return vec![];
+        };
+        first
+            .values()
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| {
+                let values: Vec<f64> = self.runs.iter().map(|r| r.values()[i].1).collect();
+                (*name, ConfidenceInterval::from_values(&values))
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        println!("==== {} replications ====", self.runs.len());
+        println!(
+            "Seeds: {}",
+            self.runs
+                .iter()
+                .map(|r| r.seed.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        println!(
+            "  {:<18} {:>12} {:>12} {:>12} {:>12}",
+            "metric", "mean", "95% CI +/-", "min", "max"
+        );
+        for (name, ci) in self.intervals() {
+            println!(
+                "  {:<18} {:>12.4} {:>12.4} {:>12.4} {:>12.4}",
+                name, ci.mean, ci.half_width, ci.min, ci.max
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommitteeAssignment;
+
+    #[test]
+    fn two_values_use_the_first_t_value() {
+        let ci = ConfidenceInterval::from_values(&[1.0, 3.0]);
+        assert_eq!((ci.mean, ci.min, ci.max), (2.0, 1.0, 3.0));
+        // The sample variance is 2, so the standard error is 1
+        assert!((ci.half_width - T_95[0]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thirty_one_values_use_the_last_t_value() {
+        let values: Vec<f64> = (0..31).map(|i| (i % 2 * 2) as f64).collect();
+        let ci = ConfidenceInterval::from_values(&values);
+        let sd = (values.iter().map(|v| (v - ci.mean).powi(2)).sum::<f64>() / 30.0).sqrt();
+        assert!((ci.half_width - T_95[29] * sd / 31f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn more_than_thirty_one_values_use_the_normal_value() {
+        let values: Vec<f64> = (0..40).map(|i| (i % 2 * 2) as f64).collect();
+        let ci = ConfidenceInterval::from_values(&values);
+        assert_eq!(ci.mean, 1.0);
+        let sd = (40.0f64 / 39.0).sqrt();
+        assert!((ci.half_width - 1.96 * sd / 40f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn one_value_has_no_interval() {
+        let ci = ConfidenceInterval::from_values(&[5.0]);
+        assert_eq!((ci.mean, ci.half_width), (5.0, 0.0));
+    }
+
+    #[test]
+    fn derived_seeds_start_with_the_seed_and_repeat() {
+        let seeds = derive_seeds(42, 5);
+        assert_eq!(seeds.len(), 5);
+        assert_eq!(seeds[0], 42);
+        assert_eq!(seeds, derive_seeds(42, 5));
+        assert_eq!(derive_seeds(42, 3), seeds[..3]);
+        assert_eq!(derive_seeds(42, 1), vec![42]);
+    }
+
+    #[tokio::test]
+    async fn committee_safety_is_the_same_for_every_replication() {
+        let config = SimulationConfig {
+            seed: Some(3),
+            num_vns: 16,
+            num_shards: 4,
+            committee_assignment: CommitteeAssignment::Random,
+            safety_trials: 200,
+            num_transactions: 5,
+            max_time: Some(std::time::Duration::from_secs(5).into()),
+            ..Default::default()
+        };
+        let replications = Replications::run(&config, 4, 1).await;
+        let safety = CommitteeSafety::from_config(&config);
+        assert!(safety.fault_bound.best() > 0.0);
+        for run in &replications.runs {
+            assert_eq!(run.p_over_fault_bound, safety.fault_bound.best());
+            assert_eq!(run.p_byzantine_quorum, safety.quorum.best());
+        }
+        let intervals = replications.intervals();
+        let (_, ci) = intervals
+            .iter()
+            .find(|(name, _)| *name == "p_over_fault_bound")
+            .unwrap();
+        assert_eq!(ci.half_width, 0.0);
+    }
+}
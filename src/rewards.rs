@@ -0,0 +1,79 @@
+use crate::config::FeeSplit;
+use crate::transaction::{Shard, Transaction};
+
+/// The part of `tx`'s fee that is paid to the VNs of `shard` when it commits there
+pub fn shard_fee(tx: &Transaction, shard: Shard, split: FeeSplit) -> f64 {
+    let fee = tx.effective_fee as f64;
+    match split {
+        FeeSplit::Equal => fee / tx.shards.len().max(1) as f64,
+        FeeSplit::First => {
+            if tx.shards.first() == Some(&shard) {
+                fee
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+/// Shares `fee` between the `leader` that proposed the committing block and the VNs that voted
+/// for it. If nobody voted the leader gets all of it.
+pub fn split_fee(fee: f64, leader: u32, votes: &[u32], leader_share: f64) -> Vec<(u32, f64)> {
+    if votes.is_empty() {
+        return vec![(leader, fee)];
+    }
+    let leader_share = leader_share.clamp(0.0, 1.0);
+    let per_voter = fee * (1.0 - leader_share) / votes.len() as f64;
+    let mut res = vec![(leader, fee * leader_share)];
+    res.extend(votes.iter().map(|v| (*v, per_voter)));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(fee: u32, shards: &[u32]) -> Transaction {
+        Transaction {
+            id: 1,
+            inputs: vec![],
+            outputs: vec![],
+            shards: shards.iter().map(|s| Shard(*s)).collect(),
+            effective_fee: fee,
+            execution_time: 0,
+            memory: 0,
+            fails: false,
+        }
+    }
+
+    #[test]
+    fn equal_split_shares_the_fee_between_shards() {
+        let tx = tx(90, &[2, 0, 1]);
+        for shard in 0..3 {
+            assert_eq!(shard_fee(&tx, Shard(shard), FeeSplit::Equal), 30.0);
+        }
+    }
+
+    #[test]
+    fn first_split_pays_only_the_first_shard() {
+        let tx = tx(90, &[2, 0]);
+        assert_eq!(shard_fee(&tx, Shard(2), FeeSplit::First), 90.0);
+        assert_eq!(shard_fee(&tx, Shard(0), FeeSplit::First), 0.0);
+    }
+
+    #[test]
+    fn leader_and_voters_share_the_fee() {
+        let split = split_fee(100.0, 1, &[1, 2, 3, 4], 0.2);
+        assert_eq!(
+            split,
+            vec![(1, 20.0), (1, 20.0), (2, 20.0), (3, 20.0), (4, 20.0)]
+        );
+        assert_eq!(split.iter().map(|(_, r)| r).sum::<f64>(), 100.0);
+    }
+
+    #[test]
+    fn leader_gets_everything_without_votes() {
+        assert_eq!(split_fee(100.0, 1, &[], 0.2), vec![(1, 100.0)]);
+        assert_eq!(split_fee(100.0, 1, &[2], 1.5), vec![(1, 100.0), (2, 0.0)]);
+    }
+}
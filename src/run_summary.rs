@@ -0,0 +1,124 @@
+use crate::committee_safety::CommitteeSafety;
+use crate::config::SimulationConfig;
+use crate::simulation::{SimulationBuilder, SimulationResult};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+
+/// The headline numbers of a run, used to compare runs against each other
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    pub seed: u64,
+    pub end_time: u128,
+    pub queued: usize,
+    pub committed: usize,
+    pub committed_tps: f64,
+    pub proposed_tps: f64,
+    pub latency_p50: u128,
+    pub latency_p90: u128,
+    pub latency_p99: u128,
+    pub leader_failures: usize,
+    pub block_requests: usize,
+    pub messages: usize,
+    /// The fraction of the run that VNs spent executing, averaged over the VNs
+    pub busy: f64,
+    /// The fees paid out over the run, divided by the number of VNs
+    pub reward_per_vn: f64,
+    /// Rewards less operating costs, averaged over the VNs
+    pub net_per_vn: f64,
+    pub vns_at_loss: usize,
+    /// The fraction of queued transactions aborted on a locked input
+    pub abort_rate: f64,
+    /// Whether the run broke a safety property and was stopped early
+    pub safety_violated: bool,
+    /// The chance that some committee has more than f Byzantine members
+    pub p_over_fault_bound: f64,
+    /// The chance that some committee has a quorum of Byzantine members
+    pub p_byzantine_quorum: f64,
+}
+
+impl RunSummary {
+    /// Sums up `result`. `safety` does not depend on the run, so it is worked out once by the
+    /// caller for every replication of a config rather than under each run's seed.
+    pub fn from_result(result: &SimulationResult, safety: &CommitteeSafety) -> Self {
+        let latency = result.latency_report().overall.end_to_end;
+        let throughput = result.throughput_report().steady_state;
+        let profit = result.profit_report();
+        Self {
+            seed: result.config.seed.unwrap_or_default(),
+            end_time: result.end_time,
+            queued: result.metrics.transactions.len(),
+            committed: result.metrics.committed_transactions().len(),
+            committed_tps: throughput.committed,
+            proposed_tps: throughput.proposed,
+            latency_p50: latency.p50,
+            latency_p90: latency.p90,
+            latency_p99: latency.p99,
+            leader_failures: result.stats.values().map(|s| s.leader_failures).sum(),
+            block_requests: result.stats.values().map(|s| s.request_block).sum(),
+            messages: result.metrics.messages.len(),
+            busy: result.stats.values().map(|s| s.busy_time).sum::<u128>() as f64
+                / (result.end_time.max(1) * result.config.num_vns.max(1) as u128) as f64,
+            reward_per_vn: result.stats.values().map(|s| s.rewards).sum::<f64>()
+                / result.config.num_vns.max(1) as f64,
+            net_per_vn: profit.mean_net(),
+            vns_at_loss: profit.at_loss(),
+            abort_rate: result.contention_report().abort_rate(),
+            safety_violated: result.safety_violation.is_some(),
+            p_over_fault_bound: safety.fault_bound.best(),
+            p_byzantine_quorum: safety.quorum.best(),
+        }
+    }
+
+    /// Every metric by name, in the order they appear in CSV output
+    pub fn values(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("end_time", self.end_time as f64),
+            ("queued", self.queued as f64),
+            ("committed", self.committed as f64),
+            ("committed_tps", self.committed_tps),
+            ("proposed_tps", self.proposed_tps),
+            ("latency_p50", self.latency_p50 as f64),
+            ("latency_p90", self.latency_p90 as f64),
+            ("latency_p99", self.latency_p99 as f64),
+            ("leader_failures", self.leader_failures as f64),
+            ("block_requests", self.block_requests as f64),
+            ("messages", self.messages as f64),
+            ("busy", self.busy),
+            ("reward_per_vn", self.reward_per_vn),
+            ("net_per_vn", self.net_per_vn),
+            ("vns_at_loss", self.vns_at_loss as f64),
+            ("abort_rate", self.abort_rate),
+            ("safety_violated", self.safety_violated as u8 as f64),
+            ("p_over_fault_bound", self.p_over_fault_bound),
+            ("p_byzantine_quorum", self.p_byzantine_quorum),
+        ]
+    }
+}
+
+/// How many runs to do at the same time if not told otherwise
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Starts a run for every config, at most `jobs` at a time, and sums it up with the committee
+/// safety given with it. The handles are in the same order as `runs`.
+pub fn spawn_runs(
+    runs: Vec<(SimulationConfig, CommitteeSafety)>,
+    jobs: usize,
+) -> Vec<JoinHandle<RunSummary>> {
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    runs.into_iter()
+        .map(|(mut config, safety)| {
+            config.print_stats_every = 0;
+            let semaphore = semaphore.clone();
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await.expect("semaphore closed");
+                let result = SimulationBuilder::from_config(config).build().run().await;
+                RunSummary::from_result(&result, &safety)
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,443 @@
+use crate::block::Block;
+use crate::observer::SimulationObserver;
+use crate::qc::Qc;
+use crate::transaction::{AbortReason, Shard};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// A HotStuff or Cerberus safety property that the run broke
+#[derive(Debug, Clone)]
+pub enum Violation {
+    /// Two VNs in a shard committed different blocks at the same height
+    ConflictingCommits {
+        shard: Shard,
+        height: u32,
+        vn_id: u32,
+        block_id: u32,
+        other_vn_id: u32,
+        other_block_id: u32,
+    },
+    /// A VN committed a block that does not extend the last block it committed
+    NotExtending {
+        shard: Shard,
+        vn_id: u32,
+        block_id: u32,
+        last_block_id: u32,
+    },
+    /// A transaction committed on one VN and aborted on another
+    ConflictingOutcomes {
+        tx_id: u32,
+        committed_by: u32,
+        committed_in: Shard,
+        aborted_by: u32,
+        aborted_in: Shard,
+        reason: AbortReason,
+    },
+    /// Two different blocks in a shard got a QC at the same height
+    ConflictingQcs {
+        shard: Shard,
+        height: u32,
+        qc_id: u32,
+        block_id: u32,
+        other_qc_id: u32,
+        other_block_id: u32,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::ConflictingCommits {
+                shard,
+                height,
+                vn_id,
+                block_id,
+                other_vn_id,
+                other_block_id,
+            } => write!(
+                f,
+                "VN {} committed block {} and VN {} committed block {} at height {} of shard {}",
+                vn_id, block_id, other_vn_id, other_block_id, height, shard.0
+            ),
+            Violation::NotExtending {
+                shard,
+                vn_id,
+                block_id,
+                last_block_id,
+            } => write!(
+                f,
+                "VN {} in shard {} committed block {}, which does not extend block {} it committed before",
+                vn_id, shard.0, block_id, last_block_id
+            ),
+            Violation::ConflictingOutcomes {
+                tx_id,
+                committed_by,
+                committed_in,
+                aborted_by,
+                aborted_in,
+                reason,
+            } => write!(
+                f,
+                "Tx {} was committed by VN {} in shard {} and aborted ({}) by VN {} in shard {}",
+                tx_id, committed_by, committed_in.0, reason, aborted_by, aborted_in.0
+            ),
+            Violation::ConflictingQcs {
+                shard,
+                height,
+                qc_id,
+                block_id,
+                other_qc_id,
+                other_block_id,
+            } => write!(
+                f,
+                "QC {} on block {} and QC {} on block {} are both at height {} of shard {}",
+                other_qc_id, other_block_id, qc_id, block_id, height, shard.0
+            ),
+        }
+    }
+}
+
+/// The first safety property the run broke, and the blocks involved
+#[derive(Debug, Clone)]
+pub struct SafetyViolation {
+    pub t: u128,
+    pub violation: Violation,
+    /// The conflicting blocks and their ancestors back to the block they have in common, newest
+    /// first. For a transaction, the blocks it was committed and aborted in
+    pub branches: Vec<Vec<Arc<Block>>>,
+}
+
+impl SafetyViolation {
+    pub fn print(&self) {
+        println!("==== Safety violation ====");
+        println!("At {}ms: {}", self.t, self.violation);
+        for (i, branch) in self.branches.iter().enumerate() {
+            println!("Branch {}:", i + 1);
+            for block in branch {
+                println!(
+                    "  block {} at height {} (parent {}, proposed by {}, justify QC {} on block {}) prepare: {:?} precommit: {:?} commit: {:?} abort: {:?}",
+                    block.id,
+                    block.height,
+                    block.parent_id,
+                    block.proposed_by,
+                    block.justify.id,
+                    block.justify.block_id,
+                    block.prepare_txs.iter().map(|t| t.id).collect::<Vec<_>>(),
+                    block.precommit_txs.iter().map(|t| t.id).collect::<Vec<_>>(),
+                    block.commit_txs.iter().map(|t| t.id).collect::<Vec<_>>(),
+                    block.abort_txs.iter().map(|(t, _)| t.id).collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+}
+
+/// How a VN finished a transaction, and in which block
+#[derive(Debug, Clone, Copy)]
+struct Outcome {
+    vn_id: u32,
+    shard: Shard,
+    block_id: u32,
+    aborted: Option<AbortReason>,
+}
+
+#[derive(Debug, Default)]
+struct SafetyState {
+    blocks: HashMap<u32, Arc<Block>>,
+    /// The block committed at each height of each shard, and the first VN that committed it
+    committed: HashMap<(Shard, u32), (u32, u32)>,
+    last_committed: HashMap<u32, Arc<Block>>,
+    /// The block that got a QC at each height of each shard, and that QC
+    qcs: HashMap<(Shard, u32), (u32, u32)>,
+    outcomes: HashMap<u32, Outcome>,
+    violation: Option<SafetyViolation>,
+}
+
+impl SafetyState {
+    /// How many blocks of each branch are kept in a violation
+    const MAX_BRANCH: usize = 10;
+
+    fn found(&mut self, t: u128, violation: Violation, branches: Vec<Vec<Arc<Block>>>) {
+        if self.violation.is_none() {
+            self.violation = Some(SafetyViolation {
+                t,
+                violation,
+                branches,
+            });
+        }
+    }
+
+    /// The first block at or below `height` on the way back from `block`
+    fn ancestor_at(&self, block: &Arc<Block>, height: u32) -> Option<u32> {
+        let mut next = Some(block.clone());
+        while let Some(block) = next {
+            if block.height <= height {
+                return Some(block.id);
+            }
+            next = self.blocks.get(&block.parent_id).cloned();
+        }
+        None
+    }
+
+    /// Both blocks and their ancestors, back to the first block they have in common
+    fn branches(&self, a: u32, b: u32) -> Vec<Vec<Arc<Block>>> {
+        let chain = |id: u32| {
+            let mut chain = vec![];
+            let mut next = self.blocks.get(&id).cloned();
+            while let Some(block) = next {
+                next = self.blocks.get(&block.parent_id).cloned();
+                chain.push(block);
+            }
+            chain
+        };
+        let (a, b) = (chain(a), chain(b));
+        let common = a
+            .iter()
+            .find(|x| b.iter().any(|y| y.id == x.id))
+            .map(|x| x.id);
+        [a, b]
+            .into_iter()
+            .map(|chain| {
+                let end = chain
+                    .iter()
+                    .position(|x| Some(x.id) == common)
+                    .map_or(chain.len(), |i| i + 1);
+                chain.into_iter().take(end.min(Self::MAX_BRANCH)).collect()
+            })
+            .collect()
+    }
+
+    fn block_committed(&mut self, vn_id: u32, block: Arc<Block>, t: u128) {
+        let key = (block.shard, block.height);
+        match self.committed.get(&key).copied() {
+            Some((block_id, other_vn_id)) if block_id != block.id => {
+                let branches = self.branches(block.id, block_id);
+                self.found(
+                    t,
+                    Violation::ConflictingCommits {
+                        shard: block.shard,
+                        height: block.height,
+                        vn_id,
+                        block_id: block.id,
+                        other_vn_id,
+                        other_block_id: block_id,
+                    },
+                    branches,
+                );
+            }
+            Some(_) => {}
+            None => {
+                self.committed.insert(key, (block.id, vn_id));
+            }
+        }
+
+        // A VN that was offline skips the blocks it missed, but what it commits next still has
+        // to descend from what it committed before
+        if let Some(last) = self.last_committed.get(&vn_id).cloned() {
+            if self.ancestor_at(&block, last.height) != Some(last.id) {
+                let branches = self.branches(block.id, last.id);
+                self.found(
+                    t,
+                    Violation::NotExtending {
+                        shard: block.shard,
+                        vn_id,
+                        block_id: block.id,
+                        last_block_id: last.id,
+                    },
+                    branches,
+                );
+            }
+        }
+        self.last_committed.insert(vn_id, block);
+    }
+
+    fn qc_created(&mut self, qc_id: u32, block_id: u32, t: u128) {
+        let Some(block) = self.blocks.get(&block_id).cloned() else {
+            return;
+        };
+        let key = (block.shard, block.height);
+        match self.qcs.get(&key).copied() {
+            Some((other_block_id, other_qc_id)) if other_block_id != block_id => {
+                let branches = self.branches(block_id, other_block_id);
+                self.found(
+                    t,
+                    Violation::ConflictingQcs {
+                        shard: block.shard,
+                        height: block.height,
+                        qc_id,
+                        block_id,
+                        other_qc_id,
+                        other_block_id,
+                    },
+                    branches,
+                );
+            }
+            Some(_) => {}
+            None => {
+                self.qcs.insert(key, (block_id, qc_id));
+            }
+        }
+    }
+
+    fn finished(&mut self, tx_id: u32, outcome: Outcome, t: u128) {
+        let Some(other) = self.outcomes.get(&tx_id).copied() else {
+            self.outcomes.insert(tx_id, outcome);
+            return;
+        };
+        let (committed, aborted, reason) = match (other.aborted, outcome.aborted) {
+            (None, Some(reason)) => (other, outcome, reason),
+            (Some(reason), None) => (outcome, other, reason),
+            _ => return,
+        };
+        let branches = [committed.block_id, aborted.block_id]
+            .iter()
+            .filter_map(|id| self.blocks.get(id).cloned())
+            .map(|block| vec![block])
+            .collect();
+        self.found(
+            t,
+            Violation::ConflictingOutcomes {
+                tx_id,
+                committed_by: committed.vn_id,
+                committed_in: committed.shard,
+                aborted_by: aborted.vn_id,
+                aborted_in: aborted.shard,
+                reason,
+            },
+            branches,
+        );
+    }
+}
+
+/// Checks the committed history as the run goes: every VN in a shard commits the same blocks in
+/// the same order, no two blocks in a shard get a QC at the same height, and every VN finishes a
+/// transaction the same way. Every VN in the simulation is honest, so any violation is a bug.
+#[derive(Debug, Clone, Default)]
+pub struct SafetyChecker {
+    inner: Arc<RwLock<SafetyState>>,
+}
+
+impl SafetyChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The first violation found, if any
+    pub async fn violation(&self) -> Option<SafetyViolation> {
+        self.inner.read().await.violation.clone()
+    }
+}
+
+#[async_trait]
+impl SimulationObserver for SafetyChecker {
+    async fn on_create_leaf(&self, block: Arc<Block>, _time: u128) {
+        self.inner.write().await.blocks.insert(block.id, block);
+    }
+
+    async fn on_qc_created(&self, qc_id: u32, _vn_id: u32, t: u128, block_id: u32) {
+        self.inner.write().await.qc_created(qc_id, block_id, t);
+    }
+
+    async fn on_block_committed(&self, vn_id: u32, block: Arc<Block>, t: u128) {
+        self.inner.write().await.block_committed(vn_id, block, t);
+    }
+
+    async fn on_transaction_committed(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        let outcome = Outcome {
+            vn_id,
+            shard,
+            block_id: in_qc.block_id,
+            aborted: None,
+        };
+        self.inner.write().await.finished(tx_id, outcome, t);
+    }
+
+    async fn on_transaction_aborted(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        reason: AbortReason,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        let outcome = Outcome {
+            vn_id,
+            shard,
+            block_id: in_qc.block_id,
+            aborted: Some(reason),
+        };
+        self.inner.write().await.finished(tx_id, outcome, t);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(id: u32, parent_id: u32, height: u32) -> Arc<Block> {
+        Arc::new(Block::new(
+            id,
+            parent_id,
+            Shard(0),
+            Arc::new(Qc::new(parent_id, parent_id, height - 1, vec![])),
+            height,
+            1,
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        ))
+    }
+
+    #[tokio::test]
+    async fn the_same_commits_are_safe() {
+        let checker = SafetyChecker::new();
+        let (a, b) = (block(1, 0, 1), block(2, 1, 2));
+        for block in [&a, &b] {
+            checker.on_create_leaf(block.clone(), 0).await;
+        }
+        for vn_id in [1, 2] {
+            checker.on_block_committed(vn_id, a.clone(), 10).await;
+            checker.on_block_committed(vn_id, b.clone(), 20).await;
+        }
+        assert!(checker.violation().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn conflicting_commits_are_found() {
+        let checker = SafetyChecker::new();
+        let (a, b) = (block(1, 0, 1), block(2, 0, 1));
+        for block in [&a, &b] {
+            checker.on_create_leaf(block.clone(), 0).await;
+        }
+        checker.on_block_committed(1, a.clone(), 10).await;
+        checker.on_block_committed(2, b.clone(), 20).await;
+
+        let violation = checker.violation().await.expect("a violation");
+        assert_eq!(violation.t, 20);
+        assert!(matches!(
+            violation.violation,
+            Violation::ConflictingCommits {
+                height: 1,
+                vn_id: 2,
+                block_id: 2,
+                other_vn_id: 1,
+                other_block_id: 1,
+                ..
+            }
+        ));
+        assert_eq!(violation.branches.len(), 2);
+    }
+}
@@ -0,0 +1,505 @@
+use crate::config::{
+    CommitteeAssignment, ExecutionTimeDistribution, Fault, FeeSplit, SimulationConfig,
+};
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A whole experiment loaded from a TOML or JSON file. Every value is optional, anything that is
+/// left out keeps its default or command line value.
+///
+/// ```toml
+/// seed = 42
+///
+/// [topology]
+/// num_vns = 40
+/// num_shards = 8
+/// vn_memory = 4096
+///
+/// [committees]
+/// assignment = "random"
+/// byzantine_fraction = 0.25
+///
+/// [network]
+/// min_latency = "20ms"
+/// max_latency = "80ms"
+/// delta = "5s"
+///
+/// [workload]
+/// num_transactions = 2000
+/// tx_per_second = 100
+/// probability_2_shards = 10
+/// execution_time_distribution = "exponential"
+/// heavy_tx_percent = 5
+///
+/// [run]
+/// max_time = "60s"
+/// until_quiescent = true
+///
+/// [rewards]
+/// fee_split = "first"
+/// leader_fee_share = 0.25
+///
+/// [costs]
+/// signature_check_time = "100us"
+/// cost_per_mb = 5
+///
+/// [[faults]]
+/// vn = 3
+/// at = "10s"
+/// recover_at = "20s"
+///
+/// [output]
+/// trace_file = "trace.jsonl"
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    pub seed: Option<u64>,
+    pub topology: TopologySection,
+    pub committees: CommitteesSection,
+    pub network: NetworkSection,
+    pub workload: WorkloadSection,
+    pub run: RunSection,
+    pub rewards: RewardsSection,
+    pub costs: CostsSection,
+    pub faults: Vec<Fault>,
+    pub output: OutputSection,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopologySection {
+    pub num_vns: Option<usize>,
+    pub num_shards: Option<u32>,
+    pub execution_threads: Option<usize>,
+    pub vn_memory: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommitteesSection {
+    pub assignment: Option<CommitteeAssignment>,
+    pub byzantine_fraction: Option<f64>,
+    pub safety_trials: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSection {
+    #[serde(with = "humantime_serde")]
+    pub min_latency: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    pub max_latency: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    pub delta: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    pub prepare_timeout: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    pub precommit_timeout: Option<Duration>,
+    pub timeout_retries: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkloadSection {
+    pub num_transactions: Option<usize>,
+    pub tx_per_second: Option<f64>,
+    pub max_block_size: Option<u64>,
+    pub max_tx_per_step_per_block: Option<usize>,
+    #[serde(with = "humantime_serde")]
+    pub execution_time: Option<Duration>,
+    pub execution_time_distribution: Option<ExecutionTimeDistribution>,
+    pub heavy_tx_percent: Option<u32>,
+    #[serde(with = "humantime_serde")]
+    pub heavy_execution_time: Option<Duration>,
+    pub tx_failure_percent: Option<u32>,
+    pub tx_memory: Option<u64>,
+    pub heavy_tx_memory: Option<u64>,
+    pub num_substates: Option<u64>,
+    pub hot_substate_percent: Option<u32>,
+    pub probability_2_shards: Option<u32>,
+    pub probability_3_shards: Option<u32>,
+    pub probability_4_shards: Option<u32>,
+    pub probability_5_shards: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunSection {
+    pub num_steps: Option<usize>,
+    #[serde(with = "humantime_serde")]
+    pub time_per_step: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    pub max_time: Option<Duration>,
+    pub until_quiescent: Option<bool>,
+    pub print_stats_every: Option<usize>,
+    #[serde(with = "humantime_serde")]
+    pub wait_for_interval: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    pub starvation_threshold: Option<Duration>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RewardsSection {
+    pub fee_split: Option<FeeSplit>,
+    pub leader_fee_share: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostsSection {
+    #[serde(with = "humantime_serde")]
+    pub signature_check_time: Option<Duration>,
+    pub cost_per_mb: Option<f64>,
+    pub cost_per_cpu_second: Option<f64>,
+    pub cost_per_mb_stored: Option<f64>,
+}
+
+/// Where the results go. These are only used by the binary.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputSection {
+    pub trace_file: Option<PathBuf>,
+    pub latency_report: Option<PathBuf>,
+    pub throughput_report: Option<PathBuf>,
+    pub memory_report: Option<PathBuf>,
+    pub neo4j_uri: Option<String>,
+    #[serde(with = "humantime_serde")]
+    pub tps_window: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    pub tps_window_step: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    pub warmup: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+}
+
+impl Display for ScenarioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioError::Io(e) => write!(f, "could not read scenario: {}", e),
+            ScenarioError::Toml(e) => write!(f, "invalid TOML scenario: {}", e),
+            ScenarioError::Json(e) => write!(f, "invalid JSON scenario: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl Scenario {
+    /// Loads a scenario, as JSON if the file ends in `.json` and as TOML otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(ScenarioError::Io)?;
+        if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&contents).map_err(ScenarioError::Json)
+        } else {
+            toml::from_str(&contents).map_err(ScenarioError::Toml)
+        }
+    }
+
+    /// Copies every value in the scenario into `config`, except for those where `overridden`
+    /// returns true for the name of the config field
+    pub fn apply_to<F: Fn(&str) -> bool>(&self, config: &mut SimulationConfig, overridden: F) {
+        fn set<T: Clone>(field: &mut T, value: &Option<T>, overridden: bool) {
+            if let (Some(value), false) = (value, overridden) {
+                *field = value.clone();
+            }
+        }
+        fn set_duration(
+            field: &mut humantime::Duration,
+            value: &Option<Duration>,
+            overridden: bool,
+        ) {
+            set(field, &value.map(Into::into), overridden);
+        }
+
+        set(&mut config.seed, &self.seed.map(Some), overridden("seed"));
+        set(
+            &mut config.num_vns,
+            &self.topology.num_vns,
+            overridden("num_vns"),
+        );
+        set(
+            &mut config.num_shards,
+            &self.topology.num_shards,
+            overridden("num_shards"),
+        );
+        set(
+            &mut config.execution_threads,
+            &self.topology.execution_threads,
+            overridden("execution_threads"),
+        );
+        set(
+            &mut config.vn_memory,
+            &self.topology.vn_memory,
+            overridden("vn_memory"),
+        );
+        set(
+            &mut config.committee_assignment,
+            &self.committees.assignment,
+            overridden("committee_assignment"),
+        );
+        set(
+            &mut config.byzantine_fraction,
+            &self.committees.byzantine_fraction,
+            overridden("byzantine_fraction"),
+        );
+        set(
+            &mut config.safety_trials,
+            &self.committees.safety_trials,
+            overridden("safety_trials"),
+        );
+        set_duration(
+            &mut config.min_latency,
+            &self.network.min_latency,
+            overridden("min_latency"),
+        );
+        set_duration(
+            &mut config.max_latency,
+            &self.network.max_latency,
+            overridden("max_latency"),
+        );
+        set_duration(&mut config.delta, &self.network.delta, overridden("delta"));
+        set_duration(
+            &mut config.prepare_timeout,
+            &self.network.prepare_timeout,
+            overridden("prepare_timeout"),
+        );
+        set_duration(
+            &mut config.precommit_timeout,
+            &self.network.precommit_timeout,
+            overridden("precommit_timeout"),
+        );
+        set(
+            &mut config.timeout_retries,
+            &self.network.timeout_retries,
+            overridden("timeout_retries"),
+        );
+
+        let workload = &self.workload;
+        set(
+            &mut config.num_transactions,
+            &workload.num_transactions,
+            overridden("num_transactions"),
+        );
+        set(
+            &mut config.tx_per_second,
+            &workload.tx_per_second.map(Some),
+            overridden("tx_per_second"),
+        );
+        set(
+            &mut config.max_block_size,
+            &workload.max_block_size,
+            overridden("max_block_size"),
+        );
+        set(
+            &mut config.max_tx_per_step_per_block,
+            &workload.max_tx_per_step_per_block,
+            overridden("max_tx_per_step_per_block"),
+        );
+        set_duration(
+            &mut config.execution_time,
+            &workload.execution_time,
+            overridden("execution_time"),
+        );
+        set(
+            &mut config.execution_time_distribution,
+            &workload.execution_time_distribution,
+            overridden("execution_time_distribution"),
+        );
+        set(
+            &mut config.heavy_tx_percent,
+            &workload.heavy_tx_percent,
+            overridden("heavy_tx_percent"),
+        );
+        set_duration(
+            &mut config.heavy_execution_time,
+            &workload.heavy_execution_time,
+            overridden("heavy_execution_time"),
+        );
+        set(
+            &mut config.tx_failure_percent,
+            &workload.tx_failure_percent,
+            overridden("tx_failure_percent"),
+        );
+        set(
+            &mut config.tx_memory,
+            &workload.tx_memory,
+            overridden("tx_memory"),
+        );
+        set(
+            &mut config.heavy_tx_memory,
+            &workload.heavy_tx_memory,
+            overridden("heavy_tx_memory"),
+        );
+        set(
+            &mut config.num_substates,
+            &workload.num_substates,
+            overridden("num_substates"),
+        );
+        set(
+            &mut config.hot_substate_percent,
+            &workload.hot_substate_percent,
+            overridden("hot_substate_percent"),
+        );
+        set(
+            &mut config.probability_2_shards,
+            &workload.probability_2_shards,
+            overridden("probability_2_shards"),
+        );
+        set(
+            &mut config.probability_3_shards,
+            &workload.probability_3_shards,
+            overridden("probability_3_shards"),
+        );
+        set(
+            &mut config.probability_4_shards,
+            &workload.probability_4_shards,
+            overridden("probability_4_shards"),
+        );
+        set(
+            &mut config.probability_5_shards,
+            &workload.probability_5_shards,
+            overridden("probability_5_shards"),
+        );
+
+        set(
+            &mut config.num_steps,
+            &self.run.num_steps,
+            overridden("num_steps"),
+        );
+        set_duration(
+            &mut config.time_per_step,
+            &self.run.time_per_step,
+            overridden("time_per_step"),
+        );
+        set(
+            &mut config.max_time,
+            &self.run.max_time.map(|t| Some(t.into())),
+            overridden("max_time"),
+        );
+        set(
+            &mut config.until_quiescent,
+            &self.run.until_quiescent,
+            overridden("until_quiescent"),
+        );
+        set(
+            &mut config.print_stats_every,
+            &self.run.print_stats_every,
+            overridden("print_stats_every"),
+        );
+        set_duration(
+            &mut config.wait_for_interval,
+            &self.run.wait_for_interval,
+            overridden("wait_for_interval"),
+        );
+        set_duration(
+            &mut config.starvation_threshold,
+            &self.run.starvation_threshold,
+            overridden("starvation_threshold"),
+        );
+
+        set(
+            &mut config.fee_split,
+            &self.rewards.fee_split,
+            overridden("fee_split"),
+        );
+        set(
+            &mut config.leader_fee_share,
+            &self.rewards.leader_fee_share,
+            overridden("leader_fee_share"),
+        );
+
+        let costs = &self.costs;
+        set_duration(
+            &mut config.signature_check_time,
+            &costs.signature_check_time,
+            overridden("signature_check_time"),
+        );
+        set(
+            &mut config.cost_per_mb,
+            &costs.cost_per_mb,
+            overridden("cost_per_mb"),
+        );
+        set(
+            &mut config.cost_per_cpu_second,
+            &costs.cost_per_cpu_second,
+            overridden("cost_per_cpu_second"),
+        );
+        set(
+            &mut config.cost_per_mb_stored,
+            &costs.cost_per_mb_stored,
+            overridden("cost_per_mb_stored"),
+        );
+
+        set_duration(
+            &mut config.tps_window,
+            &self.output.tps_window,
+            overridden("tps_window"),
+        );
+        set_duration(
+            &mut config.tps_window_step,
+            &self.output.tps_window_step,
+            overridden("tps_window_step"),
+        );
+        set_duration(
+            &mut config.warmup,
+            &self.output.warmup,
+            overridden("warmup"),
+        );
+
+        config.faults.extend(self.faults.iter().cloned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_the_example() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios/example.toml");
+        let scenario = Scenario::load(path).unwrap();
+        let mut config = SimulationConfig::default();
+        scenario.apply_to(&mut config, |_| false);
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.num_vns, 20);
+        assert_eq!(config.num_shards, 5);
+        assert_eq!(config.committee_assignment, CommitteeAssignment::RoundRobin);
+        assert_eq!(*config.min_latency, Duration::from_millis(20));
+        assert_eq!(config.tx_per_second, Some(50.0));
+        assert_eq!(config.probability_2_shards, 10);
+        assert_eq!(config.max_time.map(|t| *t), Some(Duration::from_secs(60)));
+        assert_eq!(
+            scenario.output.latency_report,
+            Some(PathBuf::from("latency.csv"))
+        );
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn keeps_overridden_values() {
+        let scenario: Scenario =
+            toml::from_str("[topology]\nnum_vns = 40\nnum_shards = 8").unwrap();
+        let mut config = SimulationConfig::default();
+        scenario.apply_to(&mut config, |name| name == "num_vns");
+        assert_eq!(config.num_vns, SimulationConfig::default().num_vns);
+        assert_eq!(config.num_shards, 8);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<Scenario>("[topology]\nnum_vn = 40").is_err());
+        assert!(toml::from_str::<Scenario>("sed = 1").is_err());
+        assert!(serde_json::from_str::<Scenario>(r#"{"network": {"latency": "1s"}}"#).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// A queue of events ordered by the time they are due. Events that are due at the same time come
+/// out in the order they were scheduled.
+#[derive(Debug)]
+pub struct Scheduler<E> {
+    queue: BinaryHeap<ScheduledEvent<E>>,
+    next_seq: u64,
+}
+
+impl<E> Scheduler<E> {
+    pub fn new() -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub fn schedule(&mut self, at: u128, event: E) {
+        self.queue.push(ScheduledEvent {
+            at,
+            seq: self.next_seq,
+            event,
+        });
+        self.next_seq += 1;
+    }
+
+    /// The time of the earliest event, if there is one
+    pub fn next_time(&self) -> Option<u128> {
+        self.queue.peek().map(|e| e.at)
+    }
+
+    /// Removes the earliest event if it is due by `time`
+    pub fn pop_due(&mut self, time: u128) -> Option<E> {
+        if self.next_time()? <= time {
+            self.queue.pop().map(|e| e.event)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ScheduledEvent<E> {
+    at: u128,
+    seq: u64,
+    event: E,
+}
+
+impl<E> PartialEq for ScheduledEvent<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at && self.seq == other.seq
+    }
+}
+
+impl<E> Eq for ScheduledEvent<E> {}
+
+impl<E> PartialOrd for ScheduledEvent<E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E> Ord for ScheduledEvent<E> {
+    // Reversed so that the max heap pops the earliest event first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_come_out_by_time_then_by_when_they_were_scheduled() {
+        let mut scheduler = Scheduler::new();
+        for (at, event) in [
+            (30, "c"),
+            (10, "a1"),
+            (20, "b"),
+            (10, "a2"),
+            (30, "d"),
+            (10, "a3"),
+        ] {
+            scheduler.schedule(at, event);
+        }
+        assert_eq!(scheduler.next_time(), Some(10));
+
+        let mut events = vec![];
+        while let Some(event) = scheduler.pop_due(u128::MAX) {
+            events.push(event);
+        }
+        assert_eq!(events, vec!["a1", "a2", "a3", "b", "c", "d"]);
+        assert_eq!(scheduler.next_time(), None);
+    }
+
+    #[test]
+    fn only_due_events_are_popped() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(20, "late");
+        scheduler.schedule(10, "early");
+        assert_eq!(scheduler.pop_due(9), None);
+        assert_eq!(scheduler.pop_due(10), Some("early"));
+        assert_eq!(scheduler.pop_due(19), None);
+        // An event scheduled for now after a later one still comes out first
+        scheduler.schedule(15, "now");
+        assert_eq!(scheduler.pop_due(20), Some("now"));
+        assert_eq!(scheduler.pop_due(20), Some("late"));
+    }
+}
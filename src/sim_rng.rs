@@ -0,0 +1,27 @@
+use rand::distributions::uniform::{SampleRange, SampleUniform};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::sync::{Arc, Mutex};
+
+/// The single source of randomness for a simulation. Every component shares the same generator
+/// so that a run can be replayed exactly from its seed.
+#[derive(Debug, Clone)]
+pub struct SimRng {
+    inner: Arc<Mutex<StdRng>>,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+        }
+    }
+
+    pub fn next_u32(&self) -> u32 {
+        self.inner.lock().unwrap().next_u32()
+    }
+
+    pub fn gen_range<T: SampleUniform, R: SampleRange<T>>(&self, range: R) -> T {
+        rand::Rng::gen_range(&mut *self.inner.lock().unwrap(), range)
+    }
+}
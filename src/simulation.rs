@@ -0,0 +1,850 @@
+use crate::block::Block;
+use crate::committee_manager::CommitteeManager;
+use crate::config::{CommitteeAssignment, Fault, SimulationConfig};
+use crate::contention_report::ContentionReport;
+use crate::execution_report::ExecutionReport;
+use crate::id_provider::IdProvider;
+use crate::indexer::Indexer;
+use crate::latency_report::LatencyReport;
+use crate::memory_report::{MemoryReport, MemorySample};
+use crate::message::Message;
+use crate::metrics::{Metrics, MetricsRecorder};
+use crate::network::Network;
+use crate::observer::SimulationObserver;
+use crate::profit::ProfitReport;
+use crate::safety_checker::{SafetyChecker, SafetyViolation};
+use crate::scheduler::Scheduler;
+use crate::sim_rng::SimRng;
+use crate::subscriber::{Stats, Subscriber};
+use crate::throughput::ThroughputReport;
+use crate::timeout_report::TimeoutReport;
+use crate::transaction::{Shard, Transaction};
+use crate::transaction_generator::TransactionGenerator;
+use crate::validator_node::{Mempool, ValidatorNode};
+use crate::wait_for::{WaitForGraph, WaitForReport};
+use log::{debug, warn};
+use rand::Rng;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Something that happens at an exact time in the simulation
+#[derive(Debug)]
+enum Event {
+    MessageDelivery {
+        to: u32,
+        message: Message,
+    },
+    /// Wakes up a VN so that it can check its timers, see `ValidatorNode::next_timeout`
+    Timeout {
+        vn_id: u32,
+    },
+    /// The next transaction from the generator is due
+    TransactionArrival,
+    /// Record the mempool backlog and print stats, once every `time_per_step`
+    Sample,
+    /// Build the wait-for graph between shards, once every `wait_for_interval`
+    WaitFor,
+    Crash {
+        vn_id: u32,
+    },
+    Recover {
+        vn_id: u32,
+    },
+}
+
+/// The total size of the mempools that hold work a VN still has to propose, taken after a step
+#[derive(Debug, Clone)]
+pub struct BacklogSample {
+    pub t: u128,
+    pub new_tx: usize,
+    pub ready_prepared: usize,
+}
+
+impl BacklogSample {
+    pub fn total(&self) -> usize {
+        self.new_tx + self.ready_prepared
+    }
+}
+
+/// A transaction that had not committed or aborted in all of its shards when the run ended
+#[derive(Debug, Clone)]
+pub struct StuckTransaction {
+    pub tx: Arc<Transaction>,
+    /// The shards in which some VN that is up has not committed or aborted it yet
+    pub pending_shards: Vec<Shard>,
+    /// Every VN that still holds it, and in which mempools
+    pub mempools: BTreeMap<u32, Vec<Mempool>>,
+}
+
+/// Everything that happened in a run
+pub struct SimulationResult {
+    /// The config the run used, with the seed filled in
+    pub config: Arc<SimulationConfig>,
+    pub end_time: u128,
+    /// Whether the run stopped because every transaction had committed
+    pub quiescent: bool,
+    /// The safety property the run broke, which stopped it there
+    pub safety_violation: Option<SafetyViolation>,
+    pub stuck: Vec<StuckTransaction>,
+    pub vns: BTreeMap<u32, ValidatorNode>,
+    pub backlog: Vec<BacklogSample>,
+    /// The memory each VN used for execution, over each `time_per_step`
+    pub memory: Vec<MemorySample>,
+    pub stats: BTreeMap<u32, Stats>,
+    pub metrics: Metrics,
+    /// The wait-for graph every `wait_for_interval`, and at the end
+    pub wait_for: Vec<WaitForGraph>,
+}
+
+/// Configures a `Simulation`. Anything that is not set keeps the value from
+/// `SimulationConfig::default()`.
+///
+/// ```no_run
+/// # async fn example() {
+/// use dansim::Simulation;
+/// use std::time::Duration;
+///
+/// let result = Simulation::builder()
+///     .seed(42)
+///     .num_vns(40)
+///     .num_shards(10)
+///     .latency(Duration::from_millis(20), Duration::from_millis(80))
+///     .workload(1000, Some(100.0))
+///     .build()
+///     .run()
+///     .await;
+/// result.latency_report().print();
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct SimulationBuilder {
+    config: SimulationConfig,
+    observers: Vec<Arc<dyn SimulationObserver>>,
+}
+
+impl SimulationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(config: SimulationConfig) -> Self {
+        Self {
+            config,
+            observers: vec![],
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    pub fn num_vns(mut self, num_vns: usize) -> Self {
+        self.config.num_vns = num_vns;
+        self
+    }
+
+    pub fn num_shards(mut self, num_shards: u32) -> Self {
+        self.config.num_shards = num_shards;
+        self
+    }
+
+    /// Each VN gets a base latency between `min` and `max`
+    pub fn latency(mut self, min: Duration, max: Duration) -> Self {
+        self.config.min_latency = min.into();
+        self.config.max_latency = max.into();
+        self
+    }
+
+    pub fn delta(mut self, delta: Duration) -> Self {
+        self.config.delta = delta.into();
+        self
+    }
+
+    pub fn max_tx_per_block(mut self, max_tx_per_block: usize) -> Self {
+        self.config.max_tx_per_step_per_block = max_tx_per_block;
+        self
+    }
+
+    /// Most bytes of transactions in a block
+    pub fn max_block_size(mut self, max_block_size: u64) -> Self {
+        self.config.max_block_size = max_block_size;
+        self
+    }
+
+    /// Generate `num_transactions`, at `tx_per_second` or all at the start if it is `None`
+    pub fn workload(mut self, num_transactions: usize, tx_per_second: Option<f64>) -> Self {
+        self.config.num_transactions = num_transactions;
+        self.config.tx_per_second = tx_per_second;
+        self
+    }
+
+    /// The percent chance that a transaction takes 2, 3, 4 and 5 inputs, each on a random shard
+    pub fn cross_shard_probabilities(mut self, probabilities: [u32; 4]) -> Self {
+        self.config.probability_2_shards = probabilities[0];
+        self.config.probability_3_shards = probabilities[1];
+        self.config.probability_4_shards = probabilities[2];
+        self.config.probability_5_shards = probabilities[3];
+        self
+    }
+
+    pub fn max_time(mut self, max_time: Duration) -> Self {
+        self.config.max_time = Some(max_time.into());
+        self
+    }
+
+    pub fn until_quiescent(mut self, until_quiescent: bool) -> Self {
+        self.config.until_quiescent = until_quiescent;
+        self
+    }
+
+    pub fn committee_assignment(mut self, committee_assignment: CommitteeAssignment) -> Self {
+        self.config.committee_assignment = committee_assignment;
+        self
+    }
+
+    pub fn fault(mut self, fault: Fault) -> Self {
+        self.config.faults.push(fault);
+        self
+    }
+
+    /// Print the stats of every VN after this many steps while running, 0 to never print
+    pub fn print_stats_every(mut self, print_stats_every: usize) -> Self {
+        self.config.print_stats_every = print_stats_every;
+        self
+    }
+
+    /// For anything that does not have its own method
+    pub fn config_mut(&mut self) -> &mut SimulationConfig {
+        &mut self.config
+    }
+
+    /// Send every event to `observer` as well. Metrics are always recorded into the result.
+    pub fn observer(mut self, observer: Arc<dyn SimulationObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    pub fn build(mut self) -> Simulation {
+        self.config
+            .seed
+            .get_or_insert_with(|| rand::thread_rng().gen());
+        Simulation {
+            config: Arc::new(self.config),
+            observers: self.observers,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Simulation {
+    config: Arc<SimulationConfig>,
+    observers: Vec<Arc<dyn SimulationObserver>>,
+}
+
+impl Simulation {
+    pub fn builder() -> SimulationBuilder {
+        SimulationBuilder::new()
+    }
+
+    pub fn config(&self) -> &SimulationConfig {
+        &self.config
+    }
+
+    /// Runs the simulation for `num_steps * time_per_step`, or `max_time` if set. Message
+    /// deliveries, VN timeouts and transaction arrivals are processed in time order at
+    /// millisecond precision, skipping over the time in between. All randomness comes from the
+    /// seed in the config.
+    pub async fn run(self) -> SimulationResult {
+        let config = self.config;
+        let metrics = MetricsRecorder::new();
+        let safety = SafetyChecker::new();
+        let mut observers: Vec<Arc<dyn SimulationObserver>> =
+            vec![Arc::new(metrics.clone()), Arc::new(safety.clone())];
+        observers.extend(self.observers);
+        let subscriber = Subscriber::new(observers);
+        let mut result = run(config, subscriber.clone(), safety).await;
+        result.stats = subscriber.stats().await;
+        result.metrics = metrics.take().await;
+        result
+    }
+}
+
+async fn run(
+    config: Arc<SimulationConfig>,
+    subscriber: Subscriber,
+    safety: SafetyChecker,
+) -> SimulationResult {
+    let rng = SimRng::new(config.seed.unwrap_or_default());
+    let mut vns = BTreeMap::new();
+    let mut network = Network::new(subscriber.clone(), rng.clone());
+    let id_provider = IdProvider::new();
+    let indexer = Indexer::new(id_provider.next());
+    subscriber.create_indexer(indexer.id).await;
+    let genesis = Arc::new(Block::genesis());
+    let committee_manager = CommitteeManager::new();
+    for s in 0..config.num_shards {
+        subscriber.create_shard(s).await;
+    }
+    let mut vn_ids = vec![];
+    for shard in assign_shards(&config, &rng) {
+        let latency = if config.min_latency.as_millis() == config.max_latency.as_millis() {
+            config.min_latency.as_millis()
+        } else {
+            rng.gen_range(config.min_latency.as_millis()..config.max_latency.as_millis())
+        };
+        let vn = ValidatorNode::new(
+            id_provider.next(),
+            shard,
+            config.clone(),
+            genesis.clone(),
+            id_provider.clone(),
+            committee_manager.clone(),
+            subscriber.clone(),
+            latency,
+        );
+        subscriber.create_vn(vn.id, vn.shard, latency).await;
+
+        network.add_connection(indexer.id, vn.id, latency, latency);
+        committee_manager.add_validator(vn.shard, vn.id).await;
+        vn_ids.push(vn.id);
+        vns.insert(vn.id, vn);
+    }
+
+    for vn in vns.values() {
+        for vninner in vns.values() {
+            if vn.id == vninner.id {
+                network.add_connection(vn.id, vninner.id, 0, 0);
+            } else {
+                network.add_connection(
+                    vn.id,
+                    vninner.id,
+                    vn.base_latency + vninner.base_latency,
+                    vn.base_latency + vninner.base_latency,
+                );
+            }
+        }
+    }
+
+    let mut transaction_generator = TransactionGenerator::new(
+        id_provider.clone(),
+        config.num_transactions,
+        config.clone(),
+        rng.clone(),
+    );
+    let time_step_millis = config.time_per_step.as_millis();
+    let mut end_time = config.end_time();
+    let mut quiescent = false;
+    let mut safety_violation = None;
+    // Transactions that have not yet committed in all of their shards
+    let mut outstanding = BTreeMap::new();
+    let mut backlog = vec![];
+    let mut memory = vec![];
+    let mut last_sample = 0;
+    let mut wait_for = vec![];
+    let wait_for_millis = config.wait_for_interval.as_millis();
+    let starvation_threshold = config.starvation_threshold.as_millis();
+
+    let mut scheduler = Scheduler::new();
+    // Timeouts that are already in the queue, so that a VN is only woken once for each
+    let mut pending_timeouts = BTreeSet::new();
+    if let Some(t) = transaction_generator.next_arrival() {
+        scheduler.schedule(t, Event::TransactionArrival);
+    }
+    for vn_id in vns.keys() {
+        pending_timeouts.insert((0, *vn_id));
+        scheduler.schedule(0, Event::Timeout { vn_id: *vn_id });
+    }
+    if time_step_millis > 0 {
+        scheduler.schedule(0, Event::Sample);
+    }
+    if wait_for_millis > 0 {
+        scheduler.schedule(wait_for_millis, Event::WaitFor);
+    }
+    let mut crashed = BTreeSet::new();
+    for fault in &config.faults {
+        let Some(vn_id) = vn_ids.get(fault.vn).cloned() else {
+            warn!(
+                "Ignoring fault for VN {}, there are only {} VNs",
+                fault.vn,
+                vn_ids.len()
+            );
+            continue;
+        };
+        scheduler.schedule(fault.at.as_millis(), Event::Crash { vn_id });
+        if let Some(recover_at) = fault.recover_at {
+            scheduler.schedule(recover_at.as_millis(), Event::Recover { vn_id });
+        }
+    }
+
+    while let Some(curr_time) = scheduler.next_time() {
+        if curr_time > end_time {
+            break;
+        }
+        debug!("Time: {:?}", curr_time);
+        let mut sample = false;
+        // loop because there are loop backs, which arrive at the same time they are sent
+        loop {
+            let mut woken = BTreeSet::new();
+            while let Some(event) = scheduler.pop_due(curr_time) {
+                match event {
+                    Event::MessageDelivery { to, message } if crashed.contains(&to) => {
+                        debug!("Message: {} dropped by crashed VN {}", message, to);
+                    }
+                    Event::MessageDelivery { to, message } => {
+                        debug!("Message: {} arrives at: {:?}", message, to);
+                        subscriber
+                            .on_message_delivered(to, &message, curr_time)
+                            .await;
+                        vns.get_mut(&to)
+                            .expect("not found")
+                            .deliver_message(message, curr_time);
+                        woken.insert(to);
+                    }
+                    Event::Timeout { vn_id } => {
+                        pending_timeouts.remove(&(curr_time, vn_id));
+                        woken.insert(vn_id);
+                    }
+                    Event::TransactionArrival => {
+                        while let Some(transaction) = transaction_generator.next(curr_time) {
+                            let transaction = Arc::new(transaction);
+                            outstanding.insert(transaction.id, transaction.clone());
+                            subscriber
+                                .on_transaction_queued(transaction.id, curr_time, &transaction)
+                                .await;
+                            for vn_id in vns.keys() {
+                                let message = Message::Transaction {
+                                    id: id_provider.next(),
+                                    tx: transaction.clone(),
+                                };
+                                let at = network
+                                    .send_message(indexer.id, *vn_id, &message, curr_time)
+                                    .await;
+                                scheduler.schedule(
+                                    at,
+                                    Event::MessageDelivery {
+                                        to: *vn_id,
+                                        message,
+                                    },
+                                );
+                            }
+                        }
+                        if let Some(t) = transaction_generator.next_arrival() {
+                            scheduler.schedule(t.max(curr_time + 1), Event::TransactionArrival);
+                        }
+                    }
+                    Event::Sample => sample = true,
+                    Event::WaitFor => {
+                        let graph = WaitForGraph::build(&vns, curr_time, starvation_threshold);
+                        let deadlocks = graph.deadlocks();
+                        if !deadlocks.is_empty() {
+                            warn!(
+                                "Shards are waiting on each other at {}ms: {:?}",
+                                curr_time, deadlocks
+                            );
+                        }
+                        wait_for.push(graph);
+                        scheduler.schedule(curr_time + wait_for_millis, Event::WaitFor);
+                    }
+                    Event::Crash { vn_id } => {
+                        if crashed.insert(vn_id) {
+                            subscriber.on_vn_crashed(vn_id, curr_time).await;
+                        }
+                    }
+                    Event::Recover { vn_id } => {
+                        if crashed.remove(&vn_id) {
+                            subscriber.on_vn_recovered(vn_id, curr_time).await;
+                            woken.insert(vn_id);
+                        }
+                    }
+                }
+            }
+            if woken.is_empty() {
+                break;
+            }
+            for vn_id in woken {
+                if crashed.contains(&vn_id) {
+                    continue;
+                }
+                let vn = vns.get_mut(&vn_id).expect("not found");
+                for (to, message) in vn.update(curr_time).await {
+                    let at = network.send_message(vn.id, to, &message, curr_time).await;
+                    scheduler.schedule(at, Event::MessageDelivery { to, message });
+                }
+                if let Some(t) = vn.next_timeout(curr_time) {
+                    if pending_timeouts.insert((t, vn_id)) {
+                        scheduler.schedule(t, Event::Timeout { vn_id });
+                    }
+                }
+            }
+        }
+
+        if let Some(violation) = safety.violation().await {
+            warn!(
+                "Stopping at {}ms, safety was broken at {}ms: {}",
+                curr_time, violation.t, violation.violation
+            );
+            safety_violation = Some(violation);
+            end_time = curr_time;
+            break;
+        }
+
+        if sample {
+            backlog.push(BacklogSample {
+                t: curr_time,
+                new_tx: vns.values().map(|vn| vn.new_tx_mempool.len()).sum(),
+                ready_prepared: vns.values().map(|vn| vn.ready_prepared_mempool.len()).sum(),
+            });
+            if curr_time > last_sample {
+                memory.push(MemorySample::take(&mut vns, last_sample, curr_time));
+                last_sample = curr_time;
+            }
+            let step = curr_time / time_step_millis + 1;
+            if step.is_multiple_of(config.print_stats_every as u128) {
+                for vn in vns.values() {
+                    vn.print_stats();
+                }
+                subscriber.print_stats().await;
+            }
+            scheduler.schedule(curr_time + time_step_millis, Event::Sample);
+        }
+
+        if config.until_quiescent && transaction_generator.next_arrival().is_none() {
+            outstanding.retain(|_, tx| !pending_shards(tx, &vns, &crashed).is_empty());
+            if outstanding.is_empty() {
+                quiescent = true;
+                end_time = curr_time;
+                break;
+            }
+        }
+    }
+
+    let mut stuck: Vec<_> = outstanding
+        .values()
+        .map(|tx| StuckTransaction {
+            tx: tx.clone(),
+            pending_shards: pending_shards(tx, &vns, &crashed),
+            mempools: BTreeMap::new(),
+        })
+        .filter(|stuck| !stuck.pending_shards.is_empty())
+        .collect();
+    if !stuck.is_empty() {
+        for vn in vns.values() {
+            let mut contents = vn.mempool_contents();
+            for s in &mut stuck {
+                if let Some(mempools) = contents.remove(&s.tx.id) {
+                    s.mempools.insert(vn.id, mempools);
+                }
+            }
+        }
+    }
+
+    if time_step_millis > 0 && end_time > last_sample {
+        memory.push(MemorySample::take(&mut vns, last_sample, end_time));
+    }
+
+    wait_for.push(WaitForGraph::build(&vns, end_time, starvation_threshold));
+
+    subscriber.on_simulation_end(end_time).await;
+    SimulationResult {
+        config,
+        end_time,
+        quiescent,
+        safety_violation,
+        stuck,
+        vns,
+        backlog,
+        memory,
+        stats: BTreeMap::new(),
+        metrics: Metrics::default(),
+        wait_for,
+    }
+}
+
+/// The shard of each VN, in the order the VNs are created
+fn assign_shards(config: &SimulationConfig, rng: &SimRng) -> Vec<Shard> {
+    let num_shards = config.num_shards;
+    match config.committee_assignment {
+        CommitteeAssignment::RoundRobin => (0..config.num_vns)
+            .map(|i| Shard(i as u32 % num_shards))
+            .collect(),
+        CommitteeAssignment::Random => {
+            let mut shards: Vec<_> = (0..config.num_vns)
+                .map(|_| Shard(rng.gen_range(0..num_shards)))
+                .collect();
+            // Give each shard a VN of its own, picked without replacement
+            let n = shards.len();
+            let mut order: Vec<_> = (0..n).collect();
+            for (s, i) in (0..num_shards).zip(0..n) {
+                order.swap(i, rng.gen_range(i..n));
+                shards[order[i]] = Shard(s);
+            }
+            shards
+        }
+    }
+}
+
+/// The shards of `tx` in which some VN that is up has not committed or aborted it yet. A shard
+/// with every VN down has not finished it either
+fn pending_shards(
+    tx: &Transaction,
+    vns: &BTreeMap<u32, ValidatorNode>,
+    crashed: &BTreeSet<u32>,
+) -> Vec<Shard> {
+    tx.shards
+        .iter()
+        .filter(|shard| {
+            let mut up = vns
+                .values()
+                .filter(|vn| vn.shard == **shard && !crashed.contains(&vn.id))
+                .peekable();
+            up.peek().is_none() || !up.all(|vn| vn.has_finished(tx.id))
+        })
+        .cloned()
+        .collect()
+}
+
+impl SimulationResult {
+    pub fn latency_report(&self) -> LatencyReport {
+        LatencyReport::from_metrics(&self.metrics)
+    }
+
+    pub fn contention_report(&self) -> ContentionReport {
+        ContentionReport::from_metrics(&self.metrics)
+    }
+
+    pub fn timeout_report(&self) -> TimeoutReport {
+        TimeoutReport::from_metrics(&self.metrics)
+    }
+
+    pub fn wait_for_report(&self) -> WaitForReport {
+        WaitForReport::from_result(self)
+    }
+
+    pub fn execution_report(&self) -> ExecutionReport {
+        ExecutionReport::from_result(self)
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport::from_result(self)
+    }
+
+    pub fn profit_report(&self) -> ProfitReport {
+        ProfitReport::from_result(self)
+    }
+
+    /// The TPS time series and steady state, using the windows and warm up from the config
+    pub fn throughput_report(&self) -> ThroughputReport {
+        ThroughputReport::from_metrics(
+            &self.metrics,
+            self.config.tps_window.as_millis(),
+            self.config.tps_window_step.as_millis(),
+            self.config.warmup.as_millis(),
+            self.end_time,
+        )
+    }
+
+    /// The final state of every VN and its `Stats`
+    pub fn print_stats(&self) {
+        for vn in self.vns.values() {
+            vn.print_stats();
+        }
+        for (id, stats) in &self.stats {
+            stats.print(*id);
+        }
+    }
+
+    pub fn print_stuck(&self) {
+        println!("==== Stuck transactions ====");
+        println!(
+            "{} transaction(s) had not committed or aborted in all shards by {}ms",
+            self.stuck.len(),
+            self.end_time
+        );
+        for stuck in &self.stuck {
+            let shards = |shards: &[Shard]| {
+                shards
+                    .iter()
+                    .map(|s| s.0.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            println!(
+                "Tx {} shards: [{}] pending in: [{}]",
+                stuck.tx.id,
+                shards(&stuck.tx.shards),
+                shards(&stuck.pending_shards)
+            );
+            if stuck.mempools.is_empty() {
+                println!("  not in any mempool");
+            }
+            for (vn_id, mempools) in &stuck.mempools {
+                println!(
+                    "  VN {}: {}",
+                    vn_id,
+                    mempools
+                        .iter()
+                        .map(|m| m.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn run(seed: u64) -> SimulationResult {
+        Simulation::builder()
+            .seed(seed)
+            .num_vns(8)
+            .num_shards(2)
+            .workload(60, Some(100.0))
+            .cross_shard_probabilities([30, 10, 0, 0])
+            .until_quiescent(true)
+            .max_time(Duration::from_secs(60))
+            .build()
+            .run()
+            .await
+    }
+
+    /// Everything a run recorded, in an order that does not depend on hashing
+    fn fingerprint(result: &SimulationResult) -> String {
+        let metrics = &result.metrics;
+        let blocks: BTreeMap<_, _> = metrics.blocks.iter().collect();
+        let qcs: BTreeMap<_, _> = metrics.qcs.iter().collect();
+        format!(
+            "{} {} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+            result.end_time,
+            result.quiescent,
+            result.stats,
+            result.backlog,
+            metrics.transactions,
+            blocks,
+            qcs,
+            metrics.votes,
+            metrics.messages,
+            metrics.lock_conflicts,
+            metrics.timeouts
+        )
+    }
+
+    #[tokio::test]
+    async fn the_same_seed_gives_the_same_run() {
+        let (first, second) = (run(9).await, run(9).await);
+        assert!(first.quiescent);
+        assert_eq!(fingerprint(&first), fingerprint(&second));
+        assert_ne!(fingerprint(&first), fingerprint(&run(10).await));
+    }
+
+    #[tokio::test]
+    async fn quiescence_waits_for_every_vn() {
+        let result = Simulation::builder()
+            .seed(42)
+            .num_vns(20)
+            .num_shards(5)
+            .workload(200, Some(50.0))
+            .cross_shard_probabilities([10, 5, 0, 0])
+            .until_quiescent(true)
+            .max_time(Duration::from_secs(60))
+            .build()
+            .run()
+            .await;
+        assert!(result.quiescent);
+        for t in result.metrics.transactions.values() {
+            for vn in result.vns.values() {
+                if t.tx.shards.contains(&vn.shard) {
+                    assert!(vn.has_finished(t.tx.id), "VN {} tx {}", vn.id, t.tx.id);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_committees_are_never_empty() {
+        let config = SimulationConfig {
+            num_vns: 10,
+            num_shards: 5,
+            committee_assignment: CommitteeAssignment::Random,
+            ..Default::default()
+        };
+        for seed in 0..200 {
+            let shards: BTreeSet<_> = assign_shards(&config, &SimRng::new(seed))
+                .into_iter()
+                .collect();
+            assert_eq!(shards.len(), 5, "seed {}", seed);
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_with_random_committees() {
+        let result = Simulation::builder()
+            .seed(11)
+            .num_vns(10)
+            .num_shards(5)
+            .committee_assignment(CommitteeAssignment::Random)
+            .workload(20, None)
+            .max_time(Duration::from_secs(30))
+            .build()
+            .run()
+            .await;
+        let shards: BTreeSet<_> = result.vns.values().map(|vn| vn.shard).collect();
+        assert_eq!(shards.len(), 5);
+        assert!(!result.metrics.committed_transactions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn blocks_stay_under_max_block_size() {
+        let result = Simulation::builder()
+            .seed(4)
+            .num_vns(4)
+            .num_shards(1)
+            .workload(30, None)
+            .max_block_size(1)
+            .until_quiescent(true)
+            .max_time(Duration::from_secs(60))
+            .build()
+            .run()
+            .await;
+        assert!(result.quiescent);
+        assert_eq!(result.metrics.committed_transactions().len(), 30);
+        // Every transaction is bigger than a byte, so only the first one fits in each block
+        let vn = result.vns.values().next().unwrap();
+        for block in vn.blocks.values() {
+            let txs = block.prepare_txs.len()
+                + block.precommit_txs.len()
+                + block.commit_txs.len()
+                + block.abort_txs.len();
+            assert!(txs <= 1, "block {} has {} transactions", block.id, txs);
+        }
+    }
+
+    #[tokio::test]
+    async fn every_transaction_commits() {
+        let result = Simulation::builder()
+            .seed(3)
+            .num_vns(8)
+            .num_shards(2)
+            .workload(50, None)
+            .cross_shard_probabilities([30, 0, 0, 0])
+            .until_quiescent(true)
+            .max_time(Duration::from_secs(60))
+            .build()
+            .run()
+            .await;
+        assert!(result.quiescent);
+        assert!(result.safety_violation.is_none());
+        assert!(result.stuck.is_empty());
+        assert_eq!(result.metrics.transactions.len(), 50);
+        assert_eq!(result.metrics.committed_transactions().len(), 50);
+        assert!(result
+            .metrics
+            .transactions
+            .values()
+            .all(|t| t.abort_reason.is_none()));
+    }
+}
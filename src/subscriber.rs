@@ -1,140 +1,122 @@
 use crate::block::Block;
+use crate::config::SimulationConfig;
+use crate::memory_report::MB;
+use crate::message::Message;
+use crate::observer::SimulationObserver;
 use crate::qc::Qc;
-use crate::transaction::{Shard, Transaction};
-use neo4rs::query;
-use neo4rs::Graph;
-use neo4rs::Node;
-use std::collections::HashMap;
+use crate::rewards;
+use crate::substate::SubstateAddress;
+use crate::transaction::{AbortReason, Shard, Transaction};
+use crate::validator_node::Mempool;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Stats {
     pub leaves_created: usize,
+    /// Fees earned as a leader or voter
+    pub rewards: f64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// The part of bytes sent and received taken up by transactions
+    pub transaction_bytes: u64,
+    pub signatures_checked: u64,
+    pub transactions_executed: u64,
+    /// Microseconds spent executing transactions
+    pub execution_time: u64,
+    /// Milliseconds spent executing before the VN could vote or propose
+    pub busy_time: u128,
+    /// The most memory in bytes used for execution at once
+    pub peak_memory: u64,
+    /// The most memory execution would have used at once with no memory limit
+    pub memory_demand: u64,
+    /// Microseconds that transactions waited for memory while a thread was free
+    pub memory_stall: u64,
+    /// Transactions that needed more memory than the VN has
+    pub over_memory_budget: u64,
+    /// Transactions this VN found had waited too long for another shard, as leader
+    pub timeouts: u64,
     pub request_block: usize,
     pub leader_failures: usize,
 }
+
+impl Stats {
+    pub fn print(&self, id: u32) {
+        println!(
+            "Stats for {}: leaves: {} rewards: {:.2} block_requests: {} leader failures: {} sent: {}B received: {}B signatures: {} executed: {} busy: {}ms peak memory: {}MB timeouts: {}",
+            id,
+            self.leaves_created,
+            self.rewards,
+            self.request_block,
+            self.leader_failures,
+            self.bytes_sent,
+            self.bytes_received,
+            self.signatures_checked,
+            self.transactions_executed,
+            self.busy_time,
+            self.peak_memory / MB,
+            self.timeouts
+        );
+    }
+}
+
+/// Handle that is cloned into every component of the simulation. It keeps the per VN `Stats` and
+/// forwards every event to the registered observers.
 #[derive(Clone)]
 pub struct Subscriber {
-    client: Arc<Graph>,
-    stats: Arc<RwLock<HashMap<u32, Stats>>>,
+    observers: Arc<Vec<Arc<dyn SimulationObserver>>>,
+    stats: Arc<RwLock<BTreeMap<u32, Stats>>>,
+    fees_paid: Arc<RwLock<HashSet<(u32, Shard)>>>,
 }
 
 impl Subscriber {
-    pub async fn on_create_leaf(&self, block: Arc<Block>, time: u128) {
-        let mut res = self.client.execute(query("CREATE (b: Block { id: $id, parent_id: $parent_id, height: $height, proposed_by: $proposed_by, t: $time })")
-            .param("id", block.id)
-            .param("time", time as u32)
-            .param("parent_id", block.parent_id)
-            .param("height", block.height)
-            .param("proposed_by", block.proposed_by)).await.expect("Failed to create block");
-        while let Ok(Some(row)) = res.next().await {
-            // println!("leaf created");
+    pub fn new(observers: Vec<Arc<dyn SimulationObserver>>) -> Self {
+        Self {
+            observers: Arc::new(observers),
+            stats: Arc::new(RwLock::new(BTreeMap::new())),
+            fees_paid: Arc::new(RwLock::new(HashSet::new())),
         }
+    }
 
-        let mut res = self.client.execute(query("MATCH (b: Block {id: $id}), (v: VN { id: $vn_id}) CREATE (v)-[n:PROPOSES { t:  $time}]->(b) RETURN n")
-            .param("id", block.id)
-            .param("time", time as u32)
-            .param("vn_id", format!("node_{}", block.proposed_by))).await.expect("Failed to create block");
-        while let Ok(Some(row)) = res.next().await {
-            // println!("rel created");
+    pub async fn on_create_leaf(&self, block: Arc<Block>, time: u128) {
+        for observer in self.observers.iter() {
+            observer.on_create_leaf(block.clone(), time).await;
         }
 
         let mut lock = self.stats.write().await;
-        lock.entry(block.proposed_by)
-            .or_insert_with(|| Stats::default())
-            .leaves_created += 1;
-
-        self.client.execute(query("MATCH (b: Block {id: $id}), (q: Qc {id: $qc_id}) CREATE (b)-[n:JUSTIFY]->(q) RETURN n")
-            .param("id", block.id)
-            .param("qc_id",  block.justify.id)).await.expect("Failed to create qc rel").next().await.expect("failed to create qc rel");
+        lock.entry(block.proposed_by).or_default().leaves_created += 1;
     }
 
     pub async fn on_vote(&self, vn_id: u32, block_id: u32, t: u128) {
-        let mut res = self.client.execute(query("MATCH (b: Block {id: $id}), (v: VN { id: $vn_id}) CREATE (v)-[n:VOTES { t:  $t}]->(b) RETURN n")
-            .param("id", block_id)
-        .param("t", t as u32)
-            .param("vn_id", format!("node_{}", vn_id))).await.expect("Failed to create block");
-
-        while let Ok(Some(row)) = res.next().await {
-            // println!("vote created");
+        for observer in self.observers.iter() {
+            observer.on_vote(vn_id, block_id, t).await;
         }
     }
 
     pub async fn create_shard(&self, shard_id: u32) {
-        let mut res = self
-            .client
-            .execute(
-                query("CREATE (s: Shard { id: $id })").param("id", format!("shard_{}", shard_id)),
-            )
-            .await
-            .expect("Failed to create shard");
-        while let Ok(Some(row)) = res.next().await {
-            // println!("shard created");
+        for observer in self.observers.iter() {
+            observer.create_shard(shard_id).await;
         }
     }
 
     pub async fn create_vn(&self, vn_id: u32, shard_id: Shard, latency: u128) {
-        let mut res = self
-            .client
-            .execute(
-                query("CREATE (vn: VN:Node { id: $id, latency: $latency })")
-                    .param("id", format!("node_{}", vn_id))
-                    .param("latency", latency as u32),
-            )
-            .await
-            .expect("Failed to create vn");
-        while let Ok(Some(row)) = res.next().await {
-            // println!("vn created");
-        }
-        let mut res = self.client
-            .execute(
-                query("MATCH (vn: VN { id: $vn_id}), (s:Shard { id: $id })  CREATE (vn)-[n:BELONGS_TO]->(s) RETURN n")
-                    .param("id", format!("shard_{}", shard_id.0))
-                    .param("vn_id", format!("node_{}", vn_id)),
-            )
-            .await
-            .expect("Could not link to shard");
-
-        while let Ok(Some(row)) = res.next().await {
-            // println!("rel created");
+        for observer in self.observers.iter() {
+            observer.create_vn(vn_id, shard_id, latency).await;
         }
     }
 
     pub async fn create_indexer(&self, id: u32) {
-        let mut res = self
-            .client
-            .execute(
-                query("CREATE (in: Indexer:Node { id: $id })").param("id", format!("node_{}", id)),
-            )
-            .await
-            .expect("Failed to create indexer");
-        while let Ok(Some(row)) = res.next().await {
-            // println!("indexer created");
+        for observer in self.observers.iter() {
+            observer.create_indexer(id).await;
         }
     }
 
     pub async fn on_transaction_queued(&self, tx_id: u32, t: u128, tx: &Transaction) {
-        self.client
-            .execute(
-                query("CREATE (t: Transaction {id: $tx_id, t: $t, shards: $shards})")
-                    .param("tx_id", tx_id)
-                    .param("t", t as u32)
-                    .param(
-                        "shards",
-                        tx.shards
-                            .iter()
-                            .map(|s| s.0.to_string())
-                            .collect::<Vec<_>>()
-                            .join(","),
-                    ),
-            )
-            .await
-            .expect("Failed to create block")
-            .next()
-            .await
-            .expect("Failed to create tx");
+        for observer in self.observers.iter() {
+            observer.on_transaction_queued(tx_id, t, tx).await;
+        }
     }
 
     pub async fn on_transaction_moved_to_prepare_ready(
@@ -144,153 +126,288 @@ impl Subscriber {
         t: u128,
         in_block: u32,
     ) {
-        self.client.execute(query("MATCH (t: Transaction {id: $tx_id}), (b: Block {id: $in_block}) CREATE (t)-[n: MOVES_TO_PREPARE_READY {t:  $t}]->(b) RETURN n").param("tx_id", tx_id)
-            .param("t", t as u32)
-            .param("in_block", in_block)).await.expect("Failed to create tx move to ready").next().await.expect("Failed to create rel");
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_moved_to_prepare_ready(tx_id, in_vn, t, in_block)
+                .await;
+        }
     }
 
-    pub async fn on_qc_created(&self, qc_id: u32, t: u128, block_id: u32) {
-        self.client
-            .execute(
-                query("MERGE (qc: Qc {id: $qc_id, t: $t})")
-                    .param("qc_id", qc_id)
-                    .param("t", t as u32),
-            )
-            .await
-            .expect("Failed to create qc")
-            .next()
-            .await
-            .expect("Failed to create qc");
+    pub async fn on_qc_created(&self, qc_id: u32, vn_id: u32, t: u128, block_id: u32) {
+        for observer in self.observers.iter() {
+            observer.on_qc_created(qc_id, vn_id, t, block_id).await;
+        }
+    }
 
-        self.client.execute(query("MATCH (b: Block {id: $id}), (qc: Qc { id: $qc_id}) CREATE (qc)-[n:QC_FOR{ t:  $t}]->(b) RETURN n").param("id", block_id)
-            .param("t", t as u32)
-            .param("qc_id", qc_id)).await.expect("Failed to create block").next().await.expect("Failed to create block");
+    pub async fn on_block_committed(&self, vn_id: u32, block: Arc<Block>, t: u128) {
+        for observer in self.observers.iter() {
+            observer.on_block_committed(vn_id, block.clone(), t).await;
+        }
     }
 
     pub async fn on_transaction_prepared_ready(
         &self,
         tx_id: u32,
+        vn_id: u32,
         shard: Shard,
         in_qc: Arc<Qc>,
         t: u128,
     ) {
-        self.client.execute(query("MATCH (q: Qc {id: $id}), (t: Transaction { id: $tx_id}) CREATE (t)-[n:PREPARED_READY_IN{ t:  $t}]->(q) RETURN n")
-            .param("id", in_qc.id)
-            .param("t", t as u32)
-            .param("tx_id", tx_id)).await.expect("Failed to create block").next().await.expect("Failed to create block");
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_prepared_ready(tx_id, vn_id, shard, in_qc.clone(), t)
+                .await;
+        }
     }
 
     pub async fn on_transaction_prepared_waiting(
         &self,
         tx_id: u32,
+        vn_id: u32,
         shard: Shard,
         in_qc: Arc<Qc>,
         t: u128,
     ) {
-        self.client.execute(query("MATCH (q: Qc {id: $id}), (t: Transaction { id: $tx_id}) CREATE (t)-[n:PREPARED_WAITING_IN { t:  $t}]->(q) RETURN n")
-            .param("id", in_qc.id)
-            .param("t", t as u32)
-            .param("tx_id", tx_id)).await.expect("Failed to create block").next().await.expect("Failed to create block");
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_prepared_waiting(tx_id, vn_id, shard, in_qc.clone(), t)
+                .await;
+        }
     }
 
     pub async fn on_transaction_precommit_ready(
         &self,
         tx_id: u32,
+        vn_id: u32,
         shard: Shard,
         in_qc: Arc<Qc>,
         t: u128,
     ) {
-        self.client.execute(query("MATCH (q: Qc{id: $id}), (t: Transaction { id: $tx_id}) CREATE (t)-[n:PRECOMMIT_READY_IN { t:  $t}]->(q) RETURN n")
-            .param("id", in_qc.id)
-            .param("t", t as u32)
-            .param("tx_id", tx_id)).await.expect("Failed to create block").next().await.expect("Failed to create block");
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_precommit_ready(tx_id, vn_id, shard, in_qc.clone(), t)
+                .await;
+        }
     }
 
     pub async fn on_transaction_precommit_waiting(
         &self,
         tx_id: u32,
+        vn_id: u32,
         shard: Shard,
         in_qc: Arc<Qc>,
         t: u128,
     ) {
-        self.client.execute(query("MATCH (q:Qc{id: $id}), (t: Transaction { id: $tx_id}) CREATE (t)-[n:PRECOMMIT_WAITING_IN { t:  $t}]->(q) RETURN n")
-            .param("id", in_qc.id)
-            .param("t", t as u32)
-            .param("tx_id", tx_id)).await.expect("Failed to create block").next().await.expect("Failed to create block");
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_precommit_waiting(tx_id, vn_id, shard, in_qc.clone(), t)
+                .await;
+        }
     }
 
     pub async fn on_transaction_committed(
         &self,
         tx_id: u32,
+        vn_id: u32,
         shard: Shard,
         in_qc: Arc<Qc>,
         t: u128,
     ) {
-        self.client.execute(query("MATCH (q: Qc {id: $id}), (t: Transaction { id: $tx_id}) CREATE (t)-[n:COMMITTED_IN { t:  $t}]->(q) RETURN n")
-            .param("id", in_qc.id)
-            .param("t", t as u32)
-            .param("tx_id", tx_id)).await.expect("Failed to create block").next().await.expect("Failed to create block");
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_committed(tx_id, vn_id, shard, in_qc.clone(), t)
+                .await;
+        }
+    }
+
+    pub async fn on_transaction_abort_vote(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        reason: AbortReason,
+        in_block: u32,
+        t: u128,
+    ) {
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_abort_vote(tx_id, vn_id, shard, reason, in_block, t)
+                .await;
+        }
+    }
+
+    pub async fn on_transaction_aborted(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        reason: AbortReason,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_aborted(tx_id, vn_id, shard, reason, in_qc.clone(), t)
+                .await;
+        }
+    }
+
+    pub async fn on_transaction_timed_out(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        mempool: Mempool,
+        aborted: bool,
+        t: u128,
+    ) {
+        for observer in self.observers.iter() {
+            observer
+                .on_transaction_timed_out(tx_id, vn_id, shard, mempool, aborted, t)
+                .await;
+        }
+
+        let mut lock = self.stats.write().await;
+        lock.entry(vn_id).or_default().timeouts += 1;
     }
 
-    pub async fn on_message_sent(
+    pub async fn on_lock_conflict(
         &self,
-        from: u32,
-        to: u32,
-        message_id: u32,
-        message: String,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        address: SubstateAddress,
+        held_by: u32,
         t: u128,
     ) {
-        let mut res = self
-            .client
-            .execute(
-                query(" CREATE (m:Message {id: $message_id, message:$message, t:$t})")
-                    .param("to", format!("node_{}", to))
-                    .param("t", t as u32)
-                    .param("message_id", message_id)
-                    .param("message", message),
-            )
-            .await
-            .expect("Failed to create message");
-        while let Ok(Some(row)) = res.next().await {
-            // println!("message created");
+        for observer in self.observers.iter() {
+            observer
+                .on_lock_conflict(tx_id, vn_id, shard, address, held_by, t)
+                .await;
         }
+    }
 
-        let mut res = self.client.execute(
-            query("MATCH (vn: Node { id: $from}), (m: Message {id: $message_id}) CREATE (vn)-[n:SENT { t:  $t}]->(m) RETURN n")
-                .param("from", format!("node_{}", from))
-                .param("to", format!("node_{}", to))
-                .param("t", t as u32)
-                .param("message_id", message_id)
-        ).await.expect("Failed to create message");
+    /// Pays `tx`'s fee for `shard` to the leader and voters of the block that committed it. Every
+    /// VN in the shard sees the same commit, so only the first call for each shard pays.
+    pub async fn pay_fee(
+        &self,
+        tx: &Transaction,
+        shard: Shard,
+        leader: u32,
+        votes: &[u32],
+        config: &SimulationConfig,
+    ) {
+        if !self.fees_paid.write().await.insert((tx.id, shard)) {
+            return;
+        }
+        let fee = rewards::shard_fee(tx, shard, config.fee_split);
+        let mut lock = self.stats.write().await;
+        for (vn_id, reward) in rewards::split_fee(fee, leader, votes, config.leader_fee_share) {
+            lock.entry(vn_id).or_default().rewards += reward;
+        }
+    }
 
-        while let Ok(Some(row)) = res.next().await {
-            // println!("message created");
+    pub async fn on_message_sent(&self, from: u32, to: u32, message: &Message, t: u128) {
+        for observer in self.observers.iter() {
+            observer.on_message_sent(from, to, message, t).await;
+        }
+    }
+
+    pub async fn on_bytes_sent(&self, from: u32, to: u32, bytes: u64, transaction_bytes: u64) {
+        let mut lock = self.stats.write().await;
+        let sender = lock.entry(from).or_default();
+        sender.bytes_sent += bytes;
+        sender.transaction_bytes += transaction_bytes;
+        let receiver = lock.entry(to).or_default();
+        receiver.bytes_received += bytes;
+        receiver.transaction_bytes += transaction_bytes;
+    }
+
+    pub async fn on_signatures_checked(&self, vn_id: u32, count: u64) {
+        let mut lock = self.stats.write().await;
+        lock.entry(vn_id).or_default().signatures_checked += count;
+    }
+
+    pub async fn on_transaction_executed(&self, vn_id: u32, execution_time: u64) {
+        let mut lock = self.stats.write().await;
+        let stats = lock.entry(vn_id).or_default();
+        stats.transactions_executed += 1;
+        stats.execution_time += execution_time;
+    }
+
+    pub async fn on_vn_busy(&self, vn_id: u32, duration: u128) {
+        let mut lock = self.stats.write().await;
+        lock.entry(vn_id).or_default().busy_time += duration;
+    }
+
+    pub async fn on_execution_memory(
+        &self,
+        vn_id: u32,
+        peak: u64,
+        demand: u64,
+        memory_stall: u64,
+        over_budget: u64,
+    ) {
+        let mut lock = self.stats.write().await;
+        let stats = lock.entry(vn_id).or_default();
+        stats.peak_memory = stats.peak_memory.max(peak);
+        stats.memory_demand = stats.memory_demand.max(demand);
+        stats.memory_stall += memory_stall;
+        stats.over_memory_budget += over_budget;
+    }
+
+    pub async fn on_message_delivered(&self, to: u32, message: &Message, t: u128) {
+        for observer in self.observers.iter() {
+            observer.on_message_delivered(to, message, t).await;
         }
     }
 
     pub async fn on_request_block(&self, id: u32) {
+        for observer in self.observers.iter() {
+            observer.on_request_block(id).await;
+        }
+
         let mut lock = self.stats.write().await;
-        lock.entry(id)
-            .or_insert_with(|| Stats::default())
-            .request_block += 1;
+        lock.entry(id).or_default().request_block += 1;
     }
 
     pub async fn on_leader_failure(&self, id: u32) {
+        for observer in self.observers.iter() {
+            observer.on_leader_failure(id).await;
+        }
+
         let mut lock = self.stats.write().await;
-        lock.entry(id)
-            .or_insert_with(|| Stats::default())
-            .leader_failures += 1;
+        lock.entry(id).or_default().leader_failures += 1;
+    }
+
+    pub async fn on_vn_crashed(&self, vn_id: u32, t: u128) {
+        for observer in self.observers.iter() {
+            observer.on_vn_crashed(vn_id, t).await;
+        }
+    }
+
+    pub async fn on_vn_recovered(&self, vn_id: u32, t: u128) {
+        for observer in self.observers.iter() {
+            observer.on_vn_recovered(vn_id, t).await;
+        }
+    }
+
+    pub async fn on_simulation_end(&self, t: u128) {
+        for observer in self.observers.iter() {
+            observer.on_simulation_end(t).await;
+        }
     }
 
     pub async fn print_stats(&self) {
         let lock = self.stats.read().await;
         for (id, stats) in lock.iter() {
-            println!(
-                "Stats for {}: leaves: {} block_requests: {} leader failures: {}",
-                id, stats.leaves_created, stats.request_block, stats.leader_failures
-            );
+            stats.print(*id);
         }
     }
+
+    pub async fn stats(&self) -> BTreeMap<u32, Stats> {
+        self.stats.read().await.clone()
+    }
 }
 
 impl Debug for Subscriber {
@@ -299,27 +416,37 @@ impl Debug for Subscriber {
     }
 }
 
-impl Subscriber {
-    pub async fn connect() -> Self {
-        // Create a Neo4j client
-        let uri = "127.0.0.1:7687";
-        let user = "neo4j";
-        let pass = "password123";
-        let client = Arc::new(Graph::new(&uri, user, pass).await.unwrap());
-
-        // clear
-
-        let mut res = client
-            .execute(query("MATCH (n) DETACH DELETE n"))
-            .await
-            .expect("Failed to clear");
-        while let Ok(Some(row)) = res.next().await {
-            println!("cleared");
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FeeSplit;
 
-        Self {
-            client,
-            stats: Arc::new(RwLock::new(HashMap::new())),
+    #[tokio::test]
+    async fn fees_are_paid_once_per_shard() {
+        let subscriber = Subscriber::new(vec![]);
+        let config = SimulationConfig {
+            fee_split: FeeSplit::Equal,
+            leader_fee_share: 0.5,
+            ..Default::default()
+        };
+        let tx = Transaction {
+            id: 1,
+            inputs: vec![],
+            outputs: vec![],
+            shards: vec![Shard(0), Shard(1)],
+            effective_fee: 100,
+            execution_time: 0,
+            memory: 0,
+            fails: false,
+        };
+        // Every VN in shard 0 sees the commit, then shard 1 commits it too
+        for _ in 0..3 {
+            subscriber.pay_fee(&tx, Shard(0), 1, &[1, 2], &config).await;
         }
+        subscriber.pay_fee(&tx, Shard(1), 3, &[3, 4], &config).await;
+
+        let stats = subscriber.stats().await;
+        let rewards: Vec<_> = stats.iter().map(|(id, s)| (*id, s.rewards)).collect();
+        assert_eq!(rewards, vec![(1, 37.5), (2, 12.5), (3, 37.5), (4, 12.5)]);
     }
 }
@@ -0,0 +1,139 @@
+use crate::transaction::{Shard, Transaction};
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// Bytes a substate address takes on the wire: its hash and version
+pub const SUBSTATE_ADDRESS_SIZE: u64 = 32 + 4;
+
+/// The address of a piece of state, e.g. a vault or a component. Addresses are spread evenly over
+/// the `u64` range, and each shard owns an equal, contiguous part of it.
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubstateAddress(pub u64);
+
+impl SubstateAddress {
+    /// The address of the `index`th of `num_substates` substates
+    pub fn from_index(index: u64, num_substates: u64) -> Self {
+        let step = u64::MAX / num_substates.max(1);
+        Self(index * step)
+    }
+
+    /// The shard that owns this address
+    pub fn shard(&self, num_shards: u32) -> Shard {
+        Shard(((self.0 as u128 * num_shards as u128) >> 64) as u32)
+    }
+
+    /// The shards that own any of `addresses`, in order and without duplicates
+    pub fn shards<'a, I: IntoIterator<Item = &'a SubstateAddress>>(
+        addresses: I,
+        num_shards: u32,
+    ) -> Vec<Shard> {
+        addresses
+            .into_iter()
+            .map(|a| a.shard(num_shards))
+            .unique()
+            .collect()
+    }
+}
+
+/// The inputs that a shard has locked for the transactions it prepared. A transaction holds its
+/// locks until it commits or aborts.
+#[derive(Debug, Clone, Default)]
+pub struct SubstateLocks {
+    locks: HashMap<SubstateAddress, u32>,
+}
+
+impl SubstateLocks {
+    /// The first input of `tx` owned by `shard` that another transaction holds, and who holds it
+    pub fn conflict(
+        &self,
+        tx: &Transaction,
+        shard: Shard,
+        num_shards: u32,
+    ) -> Option<(SubstateAddress, u32)> {
+        tx.inputs
+            .iter()
+            .filter(|a| a.shard(num_shards) == shard)
+            .find_map(|a| match self.locks.get(a) {
+                Some(holder) if *holder != tx.id => Some((*a, *holder)),
+                _ => None,
+            })
+    }
+
+    /// Locks the inputs of `tx` owned by `shard`. Inputs that are already locked keep their holder
+    pub fn lock(&mut self, tx: &Transaction, shard: Shard, num_shards: u32) {
+        for address in tx.inputs.iter().filter(|a| a.shard(num_shards) == shard) {
+            self.locks.entry(*address).or_insert(tx.id);
+        }
+    }
+
+    pub fn release(&mut self, tx_id: u32) {
+        self.locks.retain(|_, holder| *holder != tx_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.locks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(id: u32, inputs: &[SubstateAddress]) -> Transaction {
+        Transaction {
+            id,
+            inputs: inputs.to_vec(),
+            outputs: vec![],
+            shards: SubstateAddress::shards(inputs, 2),
+            effective_fee: 1,
+            execution_time: 0,
+            memory: 0,
+            fails: false,
+        }
+    }
+
+    #[test]
+    fn shards_own_equal_contiguous_ranges() {
+        assert_eq!(SubstateAddress(0).shard(4), Shard(0));
+        assert_eq!(SubstateAddress(u64::MAX / 4).shard(4), Shard(0));
+        assert_eq!(SubstateAddress(u64::MAX / 4 + 1).shard(4), Shard(1));
+        assert_eq!(SubstateAddress(u64::MAX).shard(4), Shard(3));
+        assert_eq!(SubstateAddress(u64::MAX).shard(1), Shard(0));
+
+        let shards: Vec<_> = (0..100)
+            .map(|i| SubstateAddress::from_index(i, 100).shard(4).0)
+            .collect();
+        assert!(shards.windows(2).all(|w| w[0] <= w[1]));
+        for shard in 0..4 {
+            let owned = shards.iter().filter(|s| **s == shard).count();
+            assert!((24..=26).contains(&owned), "shard {} owns {}", shard, owned);
+        }
+    }
+
+    #[test]
+    fn locks_conflict_until_released() {
+        let (a, b) = (SubstateAddress(0), SubstateAddress(u64::MAX));
+        let first = tx(1, &[a, b]);
+        let second = tx(2, &[a]);
+        let mut locks = SubstateLocks::default();
+
+        locks.lock(&first, Shard(0), 2);
+        // Only the input owned by the shard is locked
+        assert_eq!(locks.len(), 1);
+        assert_eq!(locks.conflict(&second, Shard(0), 2), Some((a, 1)));
+        assert_eq!(locks.conflict(&first, Shard(0), 2), None);
+        assert_eq!(locks.conflict(&tx(3, &[b]), Shard(1), 2), None);
+
+        // The first holder keeps the lock
+        locks.lock(&second, Shard(0), 2);
+        assert_eq!(locks.conflict(&second, Shard(0), 2), Some((a, 1)));
+
+        locks.release(1);
+        assert!(locks.is_empty());
+        assert_eq!(locks.conflict(&second, Shard(0), 2), None);
+    }
+}
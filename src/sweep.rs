@@ -0,0 +1,262 @@
+use crate::committee_safety::CommitteeSafety;
+use crate::config::SimulationConfig;
+use crate::replications::{derive_seeds, Replications};
+use crate::run_summary::{default_jobs, spawn_runs, RunSummary};
+use clap::Args;
+use itertools::Itertools;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Args, Debug, Clone)]
+pub struct SweepArgs {
+    /// A parameter to vary, as `name=a,b,c`, `name=start..end` or `name=start..end:step`. Ranges
+    /// include the end and work for whole numbers and durations, e.g. `delta=2s..10s:2s`
+    #[clap(long = "param", short = 'p', required = true)]
+    pub params: Vec<SweepParam>,
+    /// Write one CSV row per run to this file
+    #[clap(long, default_value = "sweep.csv")]
+    pub output: PathBuf,
+    /// How many runs to do at the same time. Defaults to the number of cores
+    #[clap(long)]
+    pub jobs: Option<usize>,
+}
+
+/// A config field and every value it takes in the sweep
+#[derive(Debug, Clone)]
+pub struct SweepParam {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+impl FromStr for SweepParam {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, spec) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected name=values, got {}", s))?;
+        let values = expand(spec)?;
+        // Check every value now rather than after some of the runs are done
+        let mut config = SimulationConfig::default();
+        for value in &values {
+            config.set(name, value)?;
+        }
+        Ok(Self {
+            name: name.replace('-', "_"),
+            values,
+        })
+    }
+}
+
+fn expand(spec: &str) -> Result<Vec<String>, String> {
+    let Some((start, rest)) = spec.split_once("..") else {
+        return Ok(spec.split(',').map(|v| v.trim().to_string()).collect());
+    };
+    let (end, step) = match rest.split_once(':') {
+        Some((end, step)) => (end, Some(step)),
+        None => (rest, None),
+    };
+    if let (Ok(start), Ok(end)) = (start.parse::<u64>(), end.parse::<u64>()) {
+        let step = step
+            .map(|s| {
+                s.parse::<u64>()
+                    .map_err(|e| format!("invalid step {}: {}", s, e))
+            })
+            .transpose()?
+            .unwrap_or(1);
+        if step == 0 {
+            return Err("the step of a range must be more than 0".to_string());
+        }
+        return Ok((start..=end)
+            .step_by(step as usize)
+            .map(|v| v.to_string())
+            .collect());
+    }
+
+    let duration = |s: &str| {
+        s.parse::<humantime::Duration>()
+            .map(|d| d.as_millis())
+            .map_err(|e| format!("invalid range bound {}: {}", s, e))
+    };
+    let (start, end) = (duration(start)?, duration(end)?);
+    let step = duration(step.ok_or("a range of durations needs a step, e.g. 1s..5s:1s")?)?;
+    if step == 0 {
+        return Err("the step of a range must be more than 0".to_string());
+    }
+    let mut values = vec![];
+    let mut t = start;
+    while t <= end {
+        values.push(format!("{}ms", t));
+        t += step;
+    }
+    Ok(values)
+}
+
+/// The parameter values of one point in the sweep and what came out of each replication of it
+#[derive(Debug, Clone)]
+pub struct SweepRun {
+    pub values: Vec<String>,
+    pub replications: Replications,
+}
+
+impl SweepRun {
+    /// The summary of the first replication, which uses the base seed
+    pub fn summary(&self) -> &RunSummary {
+        &self.replications.runs[0]
+    }
+}
+
+/// Runs every combination of the parameters on top of `config`, `jobs` at a time, and writes a
+/// CSV row for each as they finish in order. With more than one replication every combination is
+/// run under each seed from `derive_seeds` and the row holds the mean and 95% confidence interval
+/// of every metric.
+pub async fn run(
+    config: &SimulationConfig,
+    args: &SweepArgs,
+    replications: usize,
+) -> std::io::Result<Vec<SweepRun>> {
+    let combinations: Vec<Vec<String>> = args
+        .params
+        .iter()
+        .map(|p| p.values.clone())
+        .multi_cartesian_product()
+        .collect();
+    let jobs = args.jobs.unwrap_or_else(default_jobs);
+    let replications = replications.max(1);
+    let seeds = derive_seeds(config.seed.unwrap_or_default(), replications);
+    println!(
+        "Sweeping {} runs, {} at a time",
+        combinations.len() * replications,
+        jobs
+    );
+
+    let mut configs = vec![];
+    for values in &combinations {
+        let mut config = config.clone();
+        for (param, value) in args.params.iter().zip(values) {
+            config
+                .set(&param.name, value)
+                .expect("values are checked when parsed");
+        }
+        config.validate().map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{}: {}", values.join(" "), e),
+            )
+        })?;
+        let safety = CommitteeSafety::from_config(&config);
+        for seed in &seeds {
+            config.seed = Some(*seed);
+            configs.push((config.clone(), safety.clone()));
+        }
+    }
+    let mut handles = spawn_runs(configs, jobs).into_iter();
+
+    let mut out = BufWriter::new(File::create(&args.output)?);
+    let mut runs = vec![];
+    for (i, values) in combinations.into_iter().enumerate() {
+        let mut summaries = vec![];
+        for handle in handles.by_ref().take(replications) {
+            summaries.push(handle.await.expect("sweep run panicked"));
+        }
+        let run = SweepRun {
+            values,
+            replications: Replications { runs: summaries },
+        };
+        if i == 0 {
+            let mut header = vec!["run".to_string()];
+            header.extend(args.params.iter().map(|p| p.name.clone()));
+            header.push("seed".to_string());
+            if replications == 1 {
+                header.extend(
+                    run.summary()
+                        .values()
+                        .iter()
+                        .map(|(name, _)| name.to_string()),
+                );
+            } else {
+                header.push("replications".to_string());
+                for (name, _) in run.summary().values() {
+                    header.push(format!("{}_mean", name));
+                    header.push(format!("{}_ci95", name));
+                }
+            }
+            writeln!(out, "{}", header.join(","))?;
+        }
+        let mut row = vec![i.to_string()];
+        row.extend(run.values.iter().cloned());
+        row.push(run.summary().seed.to_string());
+        if replications == 1 {
+            row.extend(run.summary().values().iter().map(|(_, v)| v.to_string()));
+        } else {
+            row.push(replications.to_string());
+            for (_, ci) in run.replications.intervals() {
+                row.push(ci.mean.to_string());
+                row.push(ci.half_width.to_string());
+            }
+        }
+        writeln!(out, "{}", row.join(","))?;
+        out.flush()?;
+
+        let intervals = run.replications.intervals();
+        let metric = |name: &str| {
+            intervals
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, ci)| *ci)
+                .unwrap_or_default()
+        };
+        let (tps, p50) = (metric("committed_tps"), metric("latency_p50"));
+        println!(
+            "run {}: {} committed tps: {:.2} +/- {:.2} p50 latency: {:.0}ms +/- {:.0}",
+            i,
+            args.params
+                .iter()
+                .zip(&run.values)
+                .map(|(p, v)| format!("{}={}", p.name, v))
+                .join(" "),
+            tps.mean,
+            tps.half_width,
+            p50.mean,
+            p50.half_width
+        );
+        runs.push(run);
+    }
+    println!("Wrote {} runs to {}", runs.len(), args.output.display());
+    Ok(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_and_ranges_expand() {
+        assert_eq!(expand("4, 8,16").unwrap(), vec!["4", "8", "16"]);
+        assert_eq!(expand("1..3").unwrap(), vec!["1", "2", "3"]);
+        assert_eq!(expand("1..6:2").unwrap(), vec!["1", "3", "5"]);
+        assert_eq!(
+            expand("2s..6s:2s").unwrap(),
+            vec!["2000ms", "4000ms", "6000ms"]
+        );
+    }
+
+    #[test]
+    fn bad_ranges_are_rejected() {
+        assert!(expand("1..3:0").is_err());
+        assert!(expand("1s..3s").is_err());
+        assert!(expand("1s..3s:0s").is_err());
+    }
+
+    #[test]
+    fn params_are_checked_against_the_config() {
+        let param = SweepParam::from_str("num-vns=4..6").unwrap();
+        assert_eq!(param.name, "num_vns");
+        assert_eq!(param.values, vec!["4", "5", "6"]);
+        assert!(SweepParam::from_str("num_vns").is_err());
+        assert!(SweepParam::from_str("no_such_param=1").is_err());
+        assert!(SweepParam::from_str("num_vns=a,b").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use crate::metrics::{Metrics, TransactionPhase};
+use crate::transaction::Shard;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Transactions per simulated second over one window
+#[derive(Debug, Clone, Default)]
+pub struct TpsSample {
+    pub window_start: u128,
+    pub window_end: u128,
+    pub committed: f64,
+    pub proposed: f64,
+    pub committed_per_shard: BTreeMap<Shard, f64>,
+    pub proposed_per_shard: BTreeMap<Shard, f64>,
+}
+
+/// The times at which transactions were proposed and committed, overall and per shard
+#[derive(Debug, Clone, Default)]
+struct ThroughputEvents {
+    committed: Vec<u128>,
+    proposed: Vec<u128>,
+    committed_per_shard: BTreeMap<Shard, Vec<u128>>,
+    proposed_per_shard: BTreeMap<Shard, Vec<u128>>,
+}
+
+impl ThroughputEvents {
+    fn from_metrics(metrics: &Metrics) -> Self {
+        let mut res = Self::default();
+        // A transaction is proposed in a shard the first time it is in the prepare phase of a
+        // block from that shard, and proposed overall the first time it is proposed anywhere.
+        let mut first_proposed: HashMap<(u32, Shard), u128> = HashMap::new();
+        for block in metrics.blocks.values() {
+            for tx_id in &block.prepare_txs {
+                let t = first_proposed
+                    .entry((*tx_id, block.shard))
+                    .or_insert(block.created_at);
+                *t = (*t).min(block.created_at);
+            }
+        }
+        let mut first_proposed_overall: HashMap<u32, u128> = HashMap::new();
+        for ((tx_id, shard), t) in first_proposed {
+            res.proposed_per_shard.entry(shard).or_default().push(t);
+            let overall = first_proposed_overall.entry(tx_id).or_insert(t);
+            *overall = (*overall).min(t);
+        }
+        res.proposed = first_proposed_overall.into_values().collect();
+
+        for record in metrics.transactions.values() {
+            for shard in &record.tx.shards {
+                if let Some(t) = record.first_reached(TransactionPhase::Committed, *shard) {
+                    res.committed_per_shard.entry(*shard).or_default().push(t);
+                }
+            }
+            if let Some(t) = record.reached_in_all_shards(TransactionPhase::Committed) {
+                res.committed.push(t);
+            }
+        }
+        res
+    }
+}
+
+fn rate(times: &[u128], from: u128, to: u128) -> f64 {
+    if to <= from {
+        return 0.0;
+    }
+    let count = times.iter().filter(|t| **t >= from && **t < to).count();
+    count as f64 * 1000.0 / (to - from) as f64
+}
+
+fn rates(times: &BTreeMap<Shard, Vec<u128>>, from: u128, to: u128) -> BTreeMap<Shard, f64> {
+    times
+        .iter()
+        .map(|(shard, times)| (*shard, rate(times, from, to)))
+        .collect()
+}
+
+/// Committed and proposed TPS as a sliding window time series, plus the steady state rate after
+/// the warm up period
+#[derive(Debug, Clone)]
+pub struct ThroughputReport {
+    pub window: u128,
+    pub series: Vec<TpsSample>,
+    pub steady_state: TpsSample,
+}
+
+impl ThroughputReport {
+    /// `window` and `step` are the length of each window and how far it slides, in ms. The time
+    /// series covers the whole run while the steady state only starts after `warmup`.
+    pub fn from_metrics(
+        metrics: &Metrics,
+        window: u128,
+        step: u128,
+        warmup: u128,
+        end_time: u128,
+    ) -> Self {
+        let events = ThroughputEvents::from_metrics(metrics);
+        let sample = |from: u128, to: u128| TpsSample {
+            window_start: from,
+            window_end: to,
+            committed: rate(&events.committed, from, to),
+            proposed: rate(&events.proposed, from, to),
+            committed_per_shard: rates(&events.committed_per_shard, from, to),
+            proposed_per_shard: rates(&events.proposed_per_shard, from, to),
+        };
+
+        let mut series = vec![];
+        let mut window_start = 0;
+        while window > 0 && window_start + window <= end_time {
+            series.push(sample(window_start, window_start + window));
+            window_start += step.max(1);
+        }
+        Self {
+            window,
+            series,
+            steady_state: sample(warmup, end_time),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("==== Throughput (tx/s) ====");
+        println!(
+            "Steady state from {}ms to {}ms: committed: {:.2} proposed: {:.2}",
+            self.steady_state.window_start,
+            self.steady_state.window_end,
+            self.steady_state.committed,
+            self.steady_state.proposed
+        );
+        for (shard, committed) in &self.steady_state.committed_per_shard {
+            println!(
+                "  shard {}: committed: {:.2} proposed: {:.2}",
+                shard.0,
+                committed,
+                self.steady_state
+                    .proposed_per_shard
+                    .get(shard)
+                    .unwrap_or(&0.0)
+            );
+        }
+        if let Some(peak) = self
+            .series
+            .iter()
+            .max_by(|a, b| a.committed.total_cmp(&b.committed))
+        {
+            println!(
+                "Peak committed over a {}ms window: {:.2} at {}ms",
+                self.window, peak.committed, peak.window_start
+            );
+        }
+    }
+
+    /// Writes the time series as CSV, with one committed and one proposed column per shard
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        let shards: Vec<Shard> = self
+            .steady_state
+            .committed_per_shard
+            .keys()
+            .chain(self.steady_state.proposed_per_shard.keys())
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let mut header = "window_start,window_end,committed,proposed".to_string();
+        for shard in &shards {
+            header += &format!(",committed_shard_{0},proposed_shard_{0}", shard.0);
+        }
+        writeln!(file, "{}", header)?;
+        for sample in &self.series {
+            let mut row = format!(
+                "{},{},{:.3},{:.3}",
+                sample.window_start, sample.window_end, sample.committed, sample.proposed
+            );
+            for shard in &shards {
+                row += &format!(
+                    ",{:.3},{:.3}",
+                    sample.committed_per_shard.get(shard).unwrap_or(&0.0),
+                    sample.proposed_per_shard.get(shard).unwrap_or(&0.0)
+                );
+            }
+            writeln!(file, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{BlockRecord, TransactionPhaseRecord, TransactionRecord};
+    use crate::transaction::Transaction;
+
+    fn block(id: u32, shard: u32, prepare_txs: Vec<u32>, created_at: u128) -> BlockRecord {
+        BlockRecord {
+            id,
+            parent_id: 0,
+            shard: Shard(shard),
+            height: id,
+            proposed_by: 0,
+            justify_qc_id: 0,
+            prepare_txs,
+            precommit_txs: vec![],
+            commit_txs: vec![],
+            abort_txs: vec![],
+            created_at,
+        }
+    }
+
+    fn transaction(id: u32, committed: &[(u32, u128)], shards: &[u32]) -> TransactionRecord {
+        TransactionRecord {
+            tx: Transaction {
+                id,
+                inputs: vec![],
+                outputs: vec![],
+                shards: shards.iter().map(|s| Shard(*s)).collect(),
+                effective_fee: 1,
+                execution_time: 0,
+                memory: 0,
+                fails: false,
+            },
+            queued_at: 0,
+            phases: committed
+                .iter()
+                .map(|(shard, t)| TransactionPhaseRecord {
+                    phase: TransactionPhase::Committed,
+                    vn_id: 0,
+                    shard: Shard(*shard),
+                    qc_id: 0,
+                    t: *t,
+                })
+                .collect(),
+            abort_reason: None,
+        }
+    }
+
+    fn metrics() -> Metrics {
+        let mut metrics = Metrics::default();
+        for block in [
+            block(1, 0, vec![1, 2], 100),
+            block(2, 1, vec![2, 3], 300),
+            block(3, 1, vec![2], 1500),
+        ] {
+            metrics.blocks.insert(block.id, block);
+        }
+        for tx in [
+            transaction(1, &[(0, 500)], &[0]),
+            // Committed overall once the last shard commits it
+            transaction(2, &[(0, 1200), (1, 1800)], &[0, 1]),
+            transaction(3, &[], &[1]),
+        ] {
+            metrics.transactions.insert(tx.tx.id, tx);
+        }
+        metrics
+    }
+
+    #[test]
+    fn windows_count_events_from_their_start_up_to_their_end() {
+        let report = ThroughputReport::from_metrics(&metrics(), 1000, 500, 1000, 2000);
+        let windows: Vec<_> = report
+            .series
+            .iter()
+            .map(|s| (s.window_start, s.window_end, s.committed, s.proposed))
+            .collect();
+        assert_eq!(
+            windows,
+            vec![
+                (0, 1000, 1.0, 3.0),
+                (500, 1500, 1.0, 0.0),
+                (1000, 2000, 1.0, 0.0)
+            ]
+        );
+
+        let shards = |s: &BTreeMap<Shard, f64>| s.values().copied().collect::<Vec<_>>();
+        let sliding = &report.series[1];
+        assert_eq!(shards(&sliding.committed_per_shard), vec![2.0, 0.0]);
+        // A transaction proposed again in a later block counts at its first proposal
+        assert_eq!(shards(&report.series[0].proposed_per_shard), vec![2.0, 2.0]);
+        assert_eq!(shards(&report.series[2].proposed_per_shard), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn steady_state_starts_after_the_warmup() {
+        let report = ThroughputReport::from_metrics(&metrics(), 1000, 500, 500, 2500);
+        let steady = &report.steady_state;
+        assert_eq!((steady.window_start, steady.window_end), (500, 2500));
+        assert_eq!((steady.committed, steady.proposed), (1.0, 0.0));
+        assert_eq!(report.series.len(), 4);
+    }
+
+    #[test]
+    fn no_window_means_no_series() {
+        let report = ThroughputReport::from_metrics(&metrics(), 0, 500, 0, 2000);
+        assert!(report.series.is_empty());
+        assert_eq!(report.steady_state.committed, 1.0);
+    }
+}
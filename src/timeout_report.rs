@@ -0,0 +1,179 @@
+use crate::metrics::Metrics;
+use crate::transaction::Shard;
+use crate::validator_node::Mempool;
+use std::collections::{BTreeMap, HashSet};
+
+/// The transactions that timed out in one waiting mempool of one shard
+#[derive(Debug, Clone, Default)]
+pub struct PhaseTimeouts {
+    /// Transactions that timed out at least once
+    pub timed_out: usize,
+    /// How many times a block was sent again because of a timeout
+    pub retries: usize,
+    /// Transactions that the shard voted to abort because of a timeout
+    pub aborted: usize,
+}
+
+/// How many transactions waited too long on other shards, for each shard and phase
+#[derive(Debug, Clone)]
+pub struct TimeoutReport {
+    pub shards: BTreeMap<Shard, BTreeMap<Mempool, PhaseTimeouts>>,
+}
+
+impl TimeoutReport {
+    pub fn from_metrics(metrics: &Metrics) -> Self {
+        let mut shards: BTreeMap<Shard, BTreeMap<Mempool, PhaseTimeouts>> = BTreeMap::new();
+        // More than one leader can find the same transaction has timed out before its abort is
+        // applied, so transactions are only counted once
+        let mut timed_out = HashSet::new();
+        let mut aborted = HashSet::new();
+        for timeout in &metrics.timeouts {
+            let phase = shards
+                .entry(timeout.shard)
+                .or_default()
+                .entry(timeout.mempool)
+                .or_default();
+            let key = (timeout.tx_id, timeout.shard, timeout.mempool);
+            if timed_out.insert(key) {
+                phase.timed_out += 1;
+            }
+            if !timeout.aborted {
+                phase.retries += 1;
+            } else if aborted.insert(key) {
+                phase.aborted += 1;
+            }
+        }
+        Self { shards }
+    }
+
+    /// Transactions that timed out in `mempool`, summed over the shards
+    pub fn total(&self, mempool: Mempool) -> PhaseTimeouts {
+        let mut total = PhaseTimeouts::default();
+        for phase in self.shards.values().filter_map(|s| s.get(&mempool)) {
+            total.timed_out += phase.timed_out;
+            total.retries += phase.retries;
+            total.aborted += phase.aborted;
+        }
+        total
+    }
+
+    pub fn print(&self) {
+        println!("==== Timeouts ====");
+        if self.shards.is_empty() {
+            println!("No transaction waited longer than its phase's timeout");
+            return;
+        }
+        println!(
+            "  {:>5} {:<22} {:>10} {:>8} {:>8}",
+            "shard", "waiting in", "timed out", "retries", "aborted"
+        );
+        for (shard, phases) in &self.shards {
+            for (mempool, phase) in phases {
+                println!(
+                    "  {:>5} {:<22} {:>10} {:>8} {:>8}",
+                    shard.0,
+                    mempool.to_string(),
+                    phase.timed_out,
+                    phase.retries,
+                    phase.aborted
+                );
+            }
+        }
+        for mempool in [Mempool::WaitingPrepared, Mempool::WaitingPreCommitted] {
+            let total = self.total(mempool);
+            println!(
+                "{}: {} timed out, {} retries, {} aborted",
+                mempool, total.timed_out, total.retries, total.aborted
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Fault, SimulationConfig};
+    use crate::metrics::TimeoutRecord;
+    use crate::simulation::SimulationBuilder;
+    use crate::transaction::AbortReason;
+    use std::time::Duration;
+
+    #[test]
+    fn each_transaction_counts_once_per_shard_and_phase() {
+        let timeout = |tx_id, vn_id, mempool, aborted| TimeoutRecord {
+            tx_id,
+            vn_id,
+            shard: Shard(0),
+            mempool,
+            aborted,
+            t: 0,
+        };
+        let metrics = Metrics {
+            timeouts: vec![
+                timeout(1, 0, Mempool::WaitingPrepared, false),
+                timeout(1, 0, Mempool::WaitingPrepared, true),
+                // A second leader found the same abort before it was applied
+                timeout(1, 2, Mempool::WaitingPrepared, true),
+                timeout(2, 0, Mempool::WaitingPreCommitted, false),
+                timeout(2, 2, Mempool::WaitingPreCommitted, false),
+            ],
+            ..Default::default()
+        };
+        let report = TimeoutReport::from_metrics(&metrics);
+        let prepare = report.total(Mempool::WaitingPrepared);
+        assert_eq!(
+            (prepare.timed_out, prepare.retries, prepare.aborted),
+            (1, 1, 1)
+        );
+        let precommit = report.total(Mempool::WaitingPreCommitted);
+        assert_eq!(
+            (precommit.timed_out, precommit.retries, precommit.aborted),
+            (1, 2, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn a_stalled_shard_times_out_its_cross_shard_transactions() {
+        // VNs 1 and 3 are half of shard 1, so it can never form a QC again
+        let crash = |vn| Fault {
+            vn,
+            at: Duration::ZERO,
+            recover_at: None,
+        };
+        let config = SimulationConfig {
+            seed: Some(13),
+            num_vns: 8,
+            num_shards: 2,
+            num_transactions: 20,
+            probability_2_shards: 60,
+            probability_3_shards: 0,
+            faults: vec![crash(1), crash(3)],
+            prepare_timeout: Duration::from_secs(2).into(),
+            timeout_retries: 0,
+            max_time: Some(Duration::from_secs(20).into()),
+            ..Default::default()
+        };
+        let result = SimulationBuilder::from_config(config).build().run().await;
+
+        let report = result.timeout_report();
+        let prepare = report.total(Mempool::WaitingPrepared);
+        assert!(prepare.aborted > 0);
+        assert_eq!(prepare.retries, 0);
+        // Only the live shard gets as far as waiting on the other one
+        assert!(report.shards.keys().all(|s| *s == Shard(0)));
+
+        let timed_out: Vec<_> = result
+            .metrics
+            .timeouts
+            .iter()
+            .filter(|t| t.aborted)
+            .collect();
+        assert!(!timed_out.is_empty());
+        for timeout in timed_out {
+            let record = &result.metrics.transactions[&timeout.tx_id];
+            assert!(record.tx.shards.contains(&Shard(1)));
+            assert_eq!(record.abort_reason, Some(AbortReason::Timeout));
+            assert!(timeout.t >= record.queued_at + 2000);
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use crate::block::Block;
+use crate::message::Message;
+use crate::observer::SimulationObserver;
+use crate::qc::Qc;
+use crate::substate::SubstateAddress;
+use crate::transaction::{AbortReason, Shard, Transaction};
+use crate::validator_node::Mempool;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Writes every event as one JSON object per line.
+///
+/// Every line has `t` (simulation time in ms), `event`, `node` and `shard`. `node` is the VN
+/// (or indexer) the event happened on and `shard` is that node's shard, which is `null` for the
+/// indexer.
+#[derive(Debug)]
+pub struct JsonlTraceWriter {
+    inner: Mutex<JsonlTraceWriterInner>,
+}
+
+#[derive(Debug)]
+struct JsonlTraceWriterInner {
+    out: BufWriter<File>,
+    vn_shards: HashMap<u32, Shard>,
+}
+
+impl JsonlTraceWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self {
+            inner: Mutex::new(JsonlTraceWriterInner {
+                out: BufWriter::new(file),
+                vn_shards: HashMap::new(),
+            }),
+        })
+    }
+
+    async fn write(&self, t: u128, event: &str, node: Option<u32>, fields: Value) {
+        let mut inner = self.inner.lock().await;
+        let shard = node.and_then(|n| inner.vn_shards.get(&n)).map(|s| s.0);
+        let mut line = json!({
+            "t": t as u64,
+            "event": event,
+            "node": node,
+            "shard": shard,
+        });
+        if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+            line.extend(fields);
+        }
+        writeln!(inner.out, "{}", line).expect("Failed to write trace");
+    }
+
+    async fn write_phase(&self, event: &str, tx_id: u32, vn_id: u32, in_qc: Arc<Qc>, t: u128) {
+        self.write(
+            t,
+            event,
+            Some(vn_id),
+            json!({ "tx_id": tx_id, "qc_id": in_qc.id, "block_id": in_qc.block_id }),
+        )
+        .await;
+    }
+}
+
+/// The kind and id of `message` and the transaction or block it is about
+fn message_fields(message: &Message, to: Option<u32>) -> Value {
+    let (tx_id, block_id) = match message {
+        Message::Transaction { tx, .. } => (Some(tx.id), None),
+        Message::BlockProposal { block, .. } | Message::RequestBlockResponse { block, .. } => {
+            (None, Some(block.id))
+        }
+        Message::NewView { high_qc, .. } => (None, Some(high_qc.block_id)),
+        Message::Vote { block_id, .. } | Message::RequestBlock { block_id, .. } => {
+            (None, Some(*block_id))
+        }
+    };
+    let mut fields = json!({
+        "kind": message.kind().to_string(),
+        "message_id": message.id(),
+        "tx_id": tx_id,
+        "block_id": block_id,
+    });
+    if let (Some(to), Some(fields)) = (to, fields.as_object_mut()) {
+        fields.insert("to".to_string(), json!(to));
+    }
+    fields
+}
+
+#[async_trait]
+impl SimulationObserver for JsonlTraceWriter {
+    async fn create_vn(&self, vn_id: u32, shard_id: Shard, _latency: u128) {
+        self.inner.lock().await.vn_shards.insert(vn_id, shard_id);
+    }
+
+    async fn on_create_leaf(&self, block: Arc<Block>, time: u128) {
+        let ids = |txs: &Vec<Arc<Transaction>>| txs.iter().map(|t| t.id).collect::<Vec<_>>();
+        self.write(
+            time,
+            "leaf_created",
+            Some(block.proposed_by),
+            json!({
+                "block_id": block.id,
+                "parent_id": block.parent_id,
+                "height": block.height,
+                "justify_qc_id": block.justify.id,
+                "prepare_txs": ids(&block.prepare_txs),
+                "precommit_txs": ids(&block.precommit_txs),
+                "commit_txs": ids(&block.commit_txs),
+                "abort_txs": block.abort_txs.iter().map(|(t, _)| t.id).collect::<Vec<_>>(),
+                "aborted": block.aborted.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            }),
+        )
+        .await;
+    }
+
+    async fn on_vote(&self, vn_id: u32, block_id: u32, t: u128) {
+        self.write(t, "vote", Some(vn_id), json!({ "block_id": block_id }))
+            .await;
+    }
+
+    async fn on_qc_created(&self, qc_id: u32, vn_id: u32, t: u128, block_id: u32) {
+        self.write(
+            t,
+            "qc_created",
+            Some(vn_id),
+            json!({ "qc_id": qc_id, "block_id": block_id }),
+        )
+        .await;
+    }
+
+    async fn on_block_committed(&self, vn_id: u32, block: Arc<Block>, t: u128) {
+        self.write(
+            t,
+            "block_committed",
+            Some(vn_id),
+            json!({ "block_id": block.id, "shard": block.shard.0, "height": block.height }),
+        )
+        .await;
+    }
+
+    async fn on_transaction_queued(&self, tx_id: u32, t: u128, tx: &Transaction) {
+        self.write(
+            t,
+            "transaction_queued",
+            None,
+            json!({
+                "tx_id": tx_id,
+                "inputs": tx.inputs.iter().map(|a| a.0).collect::<Vec<_>>(),
+                "outputs": tx.outputs.iter().map(|a| a.0).collect::<Vec<_>>(),
+                "shards": tx.shards.iter().map(|s| s.0).collect::<Vec<_>>(),
+                "fee": tx.effective_fee,
+                "execution_time_us": tx.execution_time,
+                "fails": tx.fails,
+            }),
+        )
+        .await;
+    }
+
+    async fn on_transaction_moved_to_prepare_ready(
+        &self,
+        tx_id: u32,
+        in_vn: u32,
+        t: u128,
+        in_block: u32,
+    ) {
+        self.write(
+            t,
+            "transaction_moved_to_prepare_ready",
+            Some(in_vn),
+            json!({ "tx_id": tx_id, "block_id": in_block }),
+        )
+        .await;
+    }
+
+    async fn on_transaction_prepared_ready(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        _shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.write_phase("transaction_prepared_ready", tx_id, vn_id, in_qc, t)
+            .await;
+    }
+
+    async fn on_transaction_prepared_waiting(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        _shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.write_phase("transaction_prepared_waiting", tx_id, vn_id, in_qc, t)
+            .await;
+    }
+
+    async fn on_transaction_precommit_ready(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        _shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.write_phase("transaction_precommit_ready", tx_id, vn_id, in_qc, t)
+            .await;
+    }
+
+    async fn on_transaction_precommit_waiting(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        _shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.write_phase("transaction_precommit_waiting", tx_id, vn_id, in_qc, t)
+            .await;
+    }
+
+    async fn on_transaction_committed(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        _shard: Shard,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.write_phase("transaction_committed", tx_id, vn_id, in_qc, t)
+            .await;
+    }
+
+    async fn on_transaction_abort_vote(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        _shard: Shard,
+        reason: AbortReason,
+        in_block: u32,
+        t: u128,
+    ) {
+        self.write(
+            t,
+            "transaction_abort_vote",
+            Some(vn_id),
+            json!({
+                "tx_id": tx_id,
+                "reason": reason.to_string(),
+                "block_id": in_block,
+            }),
+        )
+        .await;
+    }
+
+    async fn on_transaction_aborted(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        _shard: Shard,
+        reason: AbortReason,
+        in_qc: Arc<Qc>,
+        t: u128,
+    ) {
+        self.write(
+            t,
+            "transaction_aborted",
+            Some(vn_id),
+            json!({
+                "tx_id": tx_id,
+                "reason": reason.to_string(),
+                "qc_id": in_qc.id,
+                "block_id": in_qc.block_id,
+            }),
+        )
+        .await;
+    }
+
+    async fn on_transaction_timed_out(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        mempool: Mempool,
+        aborted: bool,
+        t: u128,
+    ) {
+        self.write(
+            t,
+            "transaction_timed_out",
+            Some(vn_id),
+            json!({
+                "tx_id": tx_id,
+                "shard": shard.0,
+                "mempool": mempool.to_string(),
+                "aborted": aborted,
+            }),
+        )
+        .await;
+    }
+
+    async fn on_lock_conflict(
+        &self,
+        tx_id: u32,
+        vn_id: u32,
+        shard: Shard,
+        address: SubstateAddress,
+        held_by: u32,
+        t: u128,
+    ) {
+        self.write(
+            t,
+            "lock_conflict",
+            Some(vn_id),
+            json!({
+                "tx_id": tx_id,
+                "shard": shard.0,
+                "address": address.0,
+                "held_by": held_by,
+            }),
+        )
+        .await;
+    }
+
+    async fn on_message_sent(&self, from: u32, to: u32, message: &Message, t: u128) {
+        self.write(
+            t,
+            "message_sent",
+            Some(from),
+            message_fields(message, Some(to)),
+        )
+        .await;
+    }
+
+    async fn on_message_delivered(&self, to: u32, message: &Message, t: u128) {
+        self.write(
+            t,
+            "message_delivered",
+            Some(to),
+            message_fields(message, None),
+        )
+        .await;
+    }
+
+    async fn on_vn_crashed(&self, vn_id: u32, t: u128) {
+        self.write(t, "vn_crashed", Some(vn_id), json!({})).await;
+    }
+
+    async fn on_vn_recovered(&self, vn_id: u32, t: u128) {
+        self.write(t, "vn_recovered", Some(vn_id), json!({})).await;
+    }
+
+    async fn on_simulation_end(&self, _t: u128) {
+        self.inner
+            .lock()
+            .await
+            .out
+            .flush()
+            .expect("Failed to flush trace");
+    }
+}
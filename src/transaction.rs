@@ -1,4 +1,7 @@
+use crate::message::{HASH_SIZE, SIGNATURE_SIZE};
+use crate::substate::{SubstateAddress, SUBSTATE_ADDRESS_SIZE};
 use std::cmp::Ordering;
+use std::fmt::Display;
 use std::sync::Arc;
 
 #[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -7,8 +10,49 @@ pub struct Shard(pub u32);
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Transaction {
     pub id: u32,
+    /// The substates the transaction consumes
+    pub inputs: Vec<SubstateAddress>,
+    /// The substates the transaction creates
+    pub outputs: Vec<SubstateAddress>,
+    /// The shards that own any of its inputs or outputs
     pub shards: Vec<Shard>,
     pub effective_fee: u32,
+    /// Microseconds a VN spends executing the transaction
+    pub execution_time: u64,
+    /// Peak memory in bytes while the transaction executes
+    pub memory: u64,
+    /// Execution fails, so every shard votes to abort it
+    pub fails: bool,
+}
+
+impl Transaction {
+    /// Bytes on the wire and in storage: the hash, substate addresses, fee and signature
+    pub fn size(&self) -> u64 {
+        HASH_SIZE
+            + SUBSTATE_ADDRESS_SIZE * (self.inputs.len() + self.outputs.len()) as u64
+            + 8
+            + SIGNATURE_SIZE
+    }
+}
+
+/// Why a shard voted to abort a transaction
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AbortReason {
+    /// Another transaction holds one of its inputs
+    LockConflict,
+    /// It waited too long for another shard
+    Timeout,
+    ExecutionFailure,
+}
+
+impl Display for AbortReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AbortReason::LockConflict => write!(f, "lock_conflict"),
+            AbortReason::Timeout => write!(f, "timeout"),
+            AbortReason::ExecutionFailure => write!(f, "execution_failure"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -25,13 +69,7 @@ impl PartialEq<Self> for SortableByFeeTransaction {
 
 impl PartialOrd for SortableByFeeTransaction {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.fee.partial_cmp(&other.fee).and_then(|o| {
-            if o == Ordering::Equal {
-                self.tx.id.partial_cmp(&other.tx.id)
-            } else {
-                Some(o)
-            }
-        })
+        Some(self.cmp(other))
     }
 }
 
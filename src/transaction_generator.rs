@@ -1,124 +1,129 @@
-use crate::cli::Cli;
+use crate::config::{ExecutionTimeDistribution, SimulationConfig};
 use crate::id_provider::IdProvider;
-use crate::transaction::{Shard, Transaction};
+use crate::memory_report::MB;
+use crate::sim_rng::SimRng;
+use crate::substate::SubstateAddress;
+use crate::transaction::Transaction;
 use itertools::Itertools;
-use rand::{thread_rng, RngCore};
 use std::sync::Arc;
 
 pub struct TransactionGenerator {
     id_provider: IdProvider,
-    static_transactions: Vec<Transaction>,
     current_index: usize,
     num_transactions: usize,
-    config: Arc<Cli>,
+    config: Arc<SimulationConfig>,
+    rng: SimRng,
 }
 
 impl TransactionGenerator {
-    pub fn new(id_provider: IdProvider, num_transactions: usize, config: Arc<Cli>) -> Self {
+    pub fn new(
+        id_provider: IdProvider,
+        num_transactions: usize,
+        config: Arc<SimulationConfig>,
+        rng: SimRng,
+    ) -> Self {
         Self {
-            static_transactions: vec![
-                Transaction {
-                    id: id_provider.next(),
-                    shards: vec![Shard(0), Shard(1)],
-                    effective_fee: 1,
-                },
-                Transaction {
-                    id: id_provider.next(),
-                    shards: vec![Shard(0)],
-                    effective_fee: 2,
-                },
-            ],
             id_provider,
             current_index: 0,
             num_transactions,
             config,
+            rng,
         }
     }
 
-    pub fn next(&mut self) -> Option<Transaction> {
+    /// The earliest time that `next` will return another transaction
+    pub fn next_arrival(&self) -> Option<u128> {
         if self.current_index >= self.num_transactions {
             return None;
         }
-
-        // let transaction = self.static_transactions[self.current_index].clone();
-        self.current_index = self.current_index + 1;
-        if thread_rng().next_u32() % 100 < self.config.probability_5_shards {
-            let shard_1 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_2 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_3 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_4 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_5 = thread_rng().next_u32() % self.config.num_shards;
-            let shards = vec![
-                Shard(shard_1),
-                Shard(shard_2),
-                Shard(shard_3),
-                Shard(shard_4),
-                Shard(shard_5),
-            ]
-            .into_iter()
-            .unique()
-            .collect::<Vec<_>>();
-            return Some(Transaction {
-                id: self.id_provider.next(),
-                shards,
-                effective_fee: 5,
-            });
-        }
-        if thread_rng().next_u32() % 100 < self.config.probability_2_shards {
-            let shard_1 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_2 = thread_rng().next_u32() % self.config.num_shards;
-            let shards = vec![Shard(shard_1), Shard(shard_2)]
-                .into_iter()
-                .unique()
-                .collect::<Vec<_>>();
-            return Some(Transaction {
-                id: self.id_provider.next(),
-                shards,
-                effective_fee: 2,
-            });
-        }
-        if thread_rng().next_u32() % 100 < self.config.probability_3_shards {
-            let shard_1 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_2 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_3 = thread_rng().next_u32() % self.config.num_shards;
-            let shards = vec![Shard(shard_1), Shard(shard_2), Shard(shard_3)]
-                .into_iter()
-                .unique()
-                .collect::<Vec<_>>();
-            return Some(Transaction {
-                id: self.id_provider.next(),
-                shards,
-                effective_fee: 3,
-            });
+        match self.config.tx_per_second {
+            Some(tx_per_second) => {
+                Some((self.current_index as f64 * 1000.0 / tx_per_second).floor() as u128 + 1)
+            }
+            None => Some(0),
         }
+    }
 
-        if thread_rng().next_u32() % 100 < self.config.probability_4_shards {
-            let shard_1 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_2 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_3 = thread_rng().next_u32() % self.config.num_shards;
-            let shard_4 = thread_rng().next_u32() % self.config.num_shards;
-            let shards = vec![
-                Shard(shard_1),
-                Shard(shard_2),
-                Shard(shard_3),
-                Shard(shard_4),
-            ]
-            .into_iter()
-            .unique()
-            .collect::<Vec<_>>();
-            return Some(Transaction {
-                id: self.id_provider.next(),
-                shards,
-                effective_fee: 4,
-            });
+    /// The next transaction that should be queued by `current_time`, if any
+    pub fn next(&mut self, current_time: u128) -> Option<Transaction> {
+        if self.current_index >= self.num_transactions {
+            return None;
+        }
+        if let Some(tx_per_second) = self.config.tx_per_second {
+            if self.current_index as f64 >= tx_per_second * current_time as f64 / 1000.0 {
+                return None;
+            }
         }
 
-        // otherwise 1 shard
-        let shard = thread_rng().next_u32() % self.config.num_shards;
+        self.current_index += 1;
+        let (inputs, effective_fee) = self.next_inputs();
+        // Every input is replaced by a new version of itself
+        let outputs = inputs.clone();
+        let (execution_time, memory) = self.next_execution();
+        let fails = self.config.tx_failure_percent > 0
+            && self.rng.next_u32() % 100 < self.config.tx_failure_percent;
         Some(Transaction {
             id: self.id_provider.next(),
-            shards: vec![Shard(shard)],
-            effective_fee: 1,
+            shards: SubstateAddress::shards(inputs.iter().chain(&outputs), self.config.num_shards),
+            inputs,
+            outputs,
+            effective_fee,
+            execution_time,
+            memory,
+            fails,
         })
     }
+
+    /// The substates the next transaction takes as inputs, and its fee. The number of inputs is
+    /// drawn from the same probabilities that used to pick the number of shards
+    fn next_inputs(&self) -> (Vec<SubstateAddress>, u32) {
+        let count = if self.rng.next_u32() % 100 < self.config.probability_5_shards {
+            5
+        } else if self.rng.next_u32() % 100 < self.config.probability_2_shards {
+            2
+        } else if self.rng.next_u32() % 100 < self.config.probability_3_shards {
+            3
+        } else if self.rng.next_u32() % 100 < self.config.probability_4_shards {
+            4
+        } else {
+            1
+        };
+        let mut inputs = (0..count)
+            .map(|_| {
+                SubstateAddress::from_index(
+                    self.rng.gen_range(0..self.config.num_substates),
+                    self.config.num_substates,
+                )
+            })
+            .collect::<Vec<_>>();
+        if self.config.hot_substate_percent > 0
+            && self.rng.next_u32() % 100 < self.config.hot_substate_percent
+        {
+            inputs[0] = SubstateAddress::from_index(0, self.config.num_substates);
+        }
+        (inputs.into_iter().unique().collect(), count)
+    }
+
+    /// Draws the execution time of a transaction in microseconds, and its peak memory in bytes
+    fn next_execution(&self) -> (u64, u64) {
+        let heavy = self.config.heavy_tx_percent > 0
+            && self.rng.next_u32() % 100 < self.config.heavy_tx_percent;
+        let (mean, memory) = if heavy {
+            (
+                self.config.heavy_execution_time,
+                self.config.heavy_tx_memory,
+            )
+        } else {
+            (self.config.execution_time, self.config.tx_memory)
+        };
+        let mean = mean.as_micros() as f64;
+        let time = match self.config.execution_time_distribution {
+            ExecutionTimeDistribution::Fixed => mean,
+            ExecutionTimeDistribution::Uniform => self.rng.gen_range(0.0..=2.0 * mean),
+            ExecutionTimeDistribution::Exponential => {
+                -mean * (1.0 - self.rng.gen_range(0.0..1.0f64)).ln()
+            }
+        };
+        (time.round() as u64, memory * MB)
+    }
 }
@@ -1,17 +1,44 @@
 use crate::block::Block;
-use crate::cli::Cli;
 use crate::committee_manager::CommitteeManager;
+use crate::config::SimulationConfig;
+use crate::executor::Executor;
 use crate::id_provider::IdProvider;
+use crate::memory_report::{MemoryUsage, MB};
 use crate::message::Message;
-use crate::node_id::NodeId;
 use crate::qc::Qc;
 use crate::subscriber::Subscriber;
-use crate::transaction::{Shard, SortableByFeeTransaction, Transaction};
-use itertools::Itertools;
+use crate::substate::SubstateLocks;
+use crate::transaction::{AbortReason, Shard, SortableByFeeTransaction, Transaction};
 use log::*;
-use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt::Display;
 use std::sync::Arc;
 
+/// Transactions that are waiting on other shards, along with the block each shard included them in
+pub type WaitingMempool = HashMap<u32, (Arc<Transaction>, HashMap<Shard, Arc<Block>>)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Mempool {
+    NewTx,
+    WaitingPrepared,
+    ReadyPrepared,
+    WaitingPreCommitted,
+    ReadyPreCommitted,
+}
+
+impl Display for Mempool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mempool::NewTx => write!(f, "new_tx"),
+            Mempool::WaitingPrepared => write!(f, "waiting_prepared"),
+            Mempool::ReadyPrepared => write!(f, "ready_prepared"),
+            Mempool::WaitingPreCommitted => write!(f, "waiting_pre_committed"),
+            Mempool::ReadyPreCommitted => write!(f, "ready_pre_committed"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ValidatorNode {
     pub id: u32,
@@ -20,7 +47,15 @@ pub struct ValidatorNode {
     pub current_height: u32,
     pub current_leader: u32,
     pub last_proposed_round: Option<u32>,
-    pub config: Arc<Cli>,
+    last_proposed_at: Option<u128>,
+    // Set when a new QC arrives in the same ms as the last proposal
+    propose_next_ms: bool,
+    // The VN is executing transactions until then, and holds its outgoing messages
+    busy_until: u128,
+    held_outgoing: Vec<(u32, Message)>,
+    executed_transactions: HashSet<u32>,
+    executor: Executor,
+    pub config: Arc<SimulationConfig>,
     pub blocks: HashMap<u32, Arc<Block>>,
     pub b_leaf: Arc<Block>,
     pub locked_node: Arc<Block>,
@@ -37,18 +72,27 @@ pub struct ValidatorNode {
     new_view_votes: HashMap<u32, Vec<u32>>,
     // Mempools
     pub new_tx_mempool: BinaryHeap<SortableByFeeTransaction>,
-    pub waiting_prepared_mempool: HashMap<u32, (Arc<Transaction>, HashMap<Shard, Arc<Block>>)>,
+    pub waiting_prepared_mempool: WaitingMempool,
     pub ready_prepared_mempool: BinaryHeap<SortableByFeeTransaction>,
-    pub waiting_pre_committed_mempool: HashMap<u32, (Arc<Transaction>, HashMap<Shard, Arc<Block>>)>,
+    pub waiting_pre_committed_mempool: WaitingMempool,
     pub ready_pre_committed_mempool: BinaryHeap<SortableByFeeTransaction>,
     // TODO: I don't think I need a committed mempool....
+    pub committed_transactions: HashSet<u32>,
+    pub aborted_transactions: HashSet<u32>,
+    // Transactions that some shard voted to abort, and why
+    aborting: HashMap<u32, AbortReason>,
+    // When a transaction started waiting on other shards in a waiting mempool, and how many times
+    // it has been retried since
+    waiting_since: HashMap<(u32, Mempool), (u128, u32)>,
+    substate_locks: SubstateLocks,
 }
 
 impl ValidatorNode {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: u32,
         shard: Shard,
-        config: Arc<Cli>,
+        config: Arc<SimulationConfig>,
         genesis: Arc<Block>,
         id_provider: IdProvider,
         committee_manager: CommitteeManager,
@@ -68,6 +112,12 @@ impl ValidatorNode {
             current_height: 0,
             current_leader: 0,
             last_proposed_round: None,
+            last_proposed_at: None,
+            propose_next_ms: false,
+            busy_until: 0,
+            held_outgoing: vec![],
+            executed_transactions: HashSet::new(),
+            executor: Executor::new(config.execution_threads, config.vn_memory * MB),
             config,
             blocks,
             b_leaf: genesis.clone(),
@@ -84,26 +134,16 @@ impl ValidatorNode {
             base_latency,
             new_view_votes: HashMap::new(),
             ready_pre_committed_mempool: BinaryHeap::new(),
+            committed_transactions: HashSet::new(),
+            aborted_transactions: HashSet::new(),
+            aborting: HashMap::new(),
+            waiting_since: HashMap::new(),
+            substate_locks: SubstateLocks::default(),
         }
     }
 
     pub fn print_stats(&self) {
-        println!(
-            "VN {} stats: shard:{} current_leader: {} height: {} b_leaf: {}, b_exec: {}, locked_node: {}, high_qc: {}, new_tx: {} prepare_w: {} prep_r:{}, precomm_w: {}, precom_r:{}",
-            self.id,
-            self.shard.0,
-            self.current_leader,
-            self.current_height,
-            self.b_leaf.height,
-            self.b_exec.height,
-            self.locked_node.height,
-            self.high_qc.block_height,
-            self.new_tx_mempool.len(),
-            self.waiting_prepared_mempool.len(),
-            self.ready_prepared_mempool.len(),
-            self.waiting_pre_committed_mempool.len(),
-            self.ready_pre_committed_mempool.len()
-        );
+        println!("{}", self.stats());
 
         // for tx in self.new_tx_mempool.iter() {
         //     println!("new tx: {:?}", tx);
@@ -119,14 +159,79 @@ impl ValidatorNode {
         // }
     }
 
-    pub fn add_transaction(&mut self, transaction: Arc<Transaction>, at_time: u128) {
-        if transaction.shards.contains(&self.shard) {
+    pub fn stats(&self) -> String {
+        format!(
+            "VN {} stats: shard:{} current_leader: {} height: {} b_leaf: {}, b_exec: {}, locked_node: {}, high_qc: {}, new_tx: {} prepare_w: {} prep_r:{}, precomm_w: {}, precom_r:{}, locks: {}",
+            self.id,
+            self.shard.0,
+            self.current_leader,
+            self.current_height,
+            self.b_leaf.height,
+            self.b_exec.height,
+            self.locked_node.height,
+            self.high_qc.block_height,
+            self.new_tx_mempool.len(),
+            self.waiting_prepared_mempool.len(),
+            self.ready_prepared_mempool.len(),
+            self.waiting_pre_committed_mempool.len(),
+            self.ready_pre_committed_mempool.len(),
+            self.substate_locks.len()
+        )
+    }
+
+    /// Which mempools each transaction is currently in
+    pub fn mempool_contents(&self) -> HashMap<u32, Vec<Mempool>> {
+        let mut res: HashMap<u32, Vec<Mempool>> = HashMap::new();
+        let heaps = [
+            (Mempool::NewTx, &self.new_tx_mempool),
+            (Mempool::ReadyPrepared, &self.ready_prepared_mempool),
+            (
+                Mempool::ReadyPreCommitted,
+                &self.ready_pre_committed_mempool,
+            ),
+        ];
+        for (mempool, heap) in heaps {
+            for tx in heap {
+                res.entry(tx.tx.id).or_default().push(mempool);
+            }
+        }
+        let waiting = [
+            (Mempool::WaitingPrepared, &self.waiting_prepared_mempool),
+            (
+                Mempool::WaitingPreCommitted,
+                &self.waiting_pre_committed_mempool,
+            ),
+        ];
+        for (mempool, waiting) in waiting {
+            for tx_id in waiting.keys() {
+                res.entry(*tx_id).or_default().push(mempool);
+            }
+        }
+        res
+    }
+
+    /// When the transaction started waiting on other shards in `mempool`, if it still is
+    pub fn waiting_since(&self, tx_id: u32, mempool: Mempool) -> Option<u128> {
+        self.waiting_since
+            .get(&(tx_id, mempool))
+            .map(|(since, _)| *since)
+    }
+
+    /// Whether this VN has committed or aborted the transaction
+    pub fn has_finished(&self, tx_id: u32) -> bool {
+        self.committed_transactions.contains(&tx_id) || self.aborted_transactions.contains(&tx_id)
+    }
+
+    pub fn add_transaction(&mut self, transaction: Arc<Transaction>, _at_time: u128) {
+        if self.aborting.contains_key(&transaction.id) {
+            debug!("Transaction {} is already being aborted", transaction.id);
+        } else if transaction.shards.contains(&self.shard) {
             self.new_tx_mempool.push(SortableByFeeTransaction {
                 tx: transaction.clone(),
                 fee: transaction.effective_fee,
             })
         } else {
-            eprintln!(
+            debug!(
                 "Transaction {:?} does not belong to shard {:?}",
                 transaction, self.shard
             )
@@ -145,33 +250,32 @@ impl ValidatorNode {
     ) -> Vec<(u32, Message)> {
         let mut result_messages = vec![];
         if block.shard == self.shard {
-            let justify_node = match self.blocks.get(&block.justify.block_id) {
-                Some(node) => node.clone(),
-                None => {
-                    self.subscriber.on_request_block(self.id).await;
-
-                    self.snoozed_messages
-                        .entry(block.justify.block_id)
-                        .or_insert_with(Vec::new)
-                        .push(original_message);
-                    // TODO: Maybe we should ask many nodes for the block
-                    // TODO: Maybe we should provide a few blocks with the proposal, depending on space
-                    return vec![(
-                        block.proposed_by,
-                        Message::RequestBlock {
-                            id: self.id_provider.next(),
-                            block_id: block.justify.block_id,
-                            request_by: self.id,
-                        },
-                    )];
-                }
-            };
+            if let Some(missing) = self.missing_justify_block(&block) {
+                self.subscriber.on_request_block(self.id).await;
+
+                self.snoozed_messages
+                    .entry(missing)
+                    .or_default()
+                    .push(original_message);
+                // TODO: Maybe we should ask many nodes for the block
+                // TODO: Maybe we should provide a few blocks with the proposal, depending on space
+                return vec![(
+                    block.proposed_by,
+                    Message::RequestBlock {
+                        id: self.id_provider.next(),
+                        block_id: missing,
+                        request_by: self.id,
+                    },
+                )];
+            }
+            let justify_node = self.blocks[&block.justify.block_id].clone();
 
             if block.height > self.last_voted_height
                 && (self.does_extend(&block, &self.locked_node)
                     || justify_node.height > self.locked_node.height)
             {
                 self.last_voted_height = block.height;
+                self.execute(&block, current_time).await;
 
                 // send vote
                 result_messages.push((
@@ -189,15 +293,29 @@ impl ValidatorNode {
                 self.update_blocks(block, current_time).await;
             }
         } else {
-            dbg!("Foreignly");
-            // Foreign committee block
-            for tx in &block.prepare_txs {
+            debug!("Foreignly");
+            // Foreign committee block. A vote to abort from any shard decides the outcome
+            // The block also holds transactions that only involve its own shard. Only a shard
+            // the transaction involves gets a say in it
+            let shard = self.shard;
+            let from = block.shard;
+            let involved =
+                |tx: &Arc<Transaction>| tx.shards.contains(&shard) && tx.shards.contains(&from);
+            for (tx, reason) in block.abort_txs.iter().filter(|(tx, _)| involved(tx)) {
+                self.on_abort_vote(tx, *reason, block.id, current_time)
+                    .await;
+            }
+            for tx in block.prepare_txs.iter().filter(|tx| involved(tx)) {
+                // A block that is sent again may hold transactions that have moved on
+                if self.aborting.contains_key(&tx.id) || self.is_prepared(tx.id) {
+                    continue;
+                }
                 // check if we have a prepare waiting
                 let mut must_remove = false;
                 if let Some((tx, shard_nodes)) = self.waiting_prepared_mempool.get_mut(&tx.id) {
-                    if !shard_nodes.contains_key(&block.shard) {
-                        dbg!("Added vote");
-                        shard_nodes.insert(block.shard, block.clone());
+                    if let Entry::Vacant(e) = shard_nodes.entry(block.shard) {
+                        debug!("Added vote");
+                        e.insert(block.clone());
                         // check if we can move it to ready
                         if shard_nodes.len() == tx.shards.len() {
                             self.ready_prepared_mempool.push(SortableByFeeTransaction {
@@ -205,14 +323,15 @@ impl ValidatorNode {
                                 fee: tx.effective_fee,
                             });
                             must_remove = true;
-                            // self.waiting_prepared_mempool.remove(&tx.id);
                             // TODO: attach all nodes to the tx
-                            self.subscriber.on_transaction_moved_to_prepare_ready(
-                                tx.id,
-                                self.id,
-                                current_time,
-                                block.id,
-                            );
+                            self.subscriber
+                                .on_transaction_moved_to_prepare_ready(
+                                    tx.id,
+                                    self.id,
+                                    current_time,
+                                    block.id,
+                                )
+                                .await;
                         }
                     }
                 } else {
@@ -230,11 +349,59 @@ impl ValidatorNode {
                     self.waiting_prepared_mempool.remove(&tx.id);
                 }
             }
+            // The other shards have to see this shard's precommit before they can commit
+            for tx in block.precommit_txs.iter().filter(|tx| involved(tx)) {
+                if self.aborting.contains_key(&tx.id)
+                    || self.committed_transactions.contains(&tx.id)
+                {
+                    continue;
+                }
+                // The other shard precommits the transaction as aborted once it knows of an abort
+                if let Some(reason) = block.abort_reason(tx.id) {
+                    self.on_abort_vote(tx, reason, block.id, current_time).await;
+                    continue;
+                }
+                let entry = self
+                    .waiting_pre_committed_mempool
+                    .entry(tx.id)
+                    .or_insert_with(|| (tx.clone(), HashMap::new()));
+                entry.1.insert(block.shard, block.clone());
+                if entry.1.len() == tx.shards.len() {
+                    self.ready_pre_committed_mempool
+                        .push(SortableByFeeTransaction {
+                            tx: tx.clone(),
+                            fee: tx.effective_fee,
+                        });
+                    self.waiting_pre_committed_mempool.remove(&tx.id);
+                    // The foreign block has no QC of its own yet, so the QC it carries stands in
+                    self.subscriber
+                        .on_transaction_precommit_ready(
+                            tx.id,
+                            self.id,
+                            self.shard,
+                            block.justify.clone(),
+                            current_time,
+                        )
+                        .await;
+                }
+            }
         }
 
         result_messages
     }
 
+    /// The first block in the 3 chain of justifies behind `block` that this VN does not have
+    fn missing_justify_block(&self, block: &Block) -> Option<u32> {
+        let mut block_id = block.justify.block_id;
+        for _ in 0..3 {
+            match self.blocks.get(&block_id) {
+                Some(justified) => block_id = justified.justify.block_id,
+                None => return Some(block_id),
+            }
+        }
+        None
+    }
+
     async fn update_blocks(&mut self, block: Arc<Block>, current_time: u128) {
         self.current_height = block.height;
 
@@ -252,7 +419,7 @@ impl ValidatorNode {
         self.apply_qc(&block.justify, current_time, &b_dash_dash)
             .await;
 
-        /// other hotstuff
+        // other hotstuff
         let b_dash = self
             .blocks
             .get(&b_dash_dash.justify.block_id)
@@ -272,13 +439,22 @@ impl ValidatorNode {
 
         // Only commit when we have formed a 3 chain, but then commit everything
         if b_dash_dash.parent_id == b_dash.id && b_dash.parent_id == b.id {
-            self.on_commit(b.clone());
+            self.on_commit(b.clone(), current_time).await;
             self.b_exec = b.clone();
         }
     }
 
     async fn apply_qc(&mut self, qc: &Arc<Qc>, current_time: u128, justified_node: &Arc<Block>) {
+        for (tx, reason) in &justified_node.abort_txs {
+            self.on_abort_vote(tx, *reason, justified_node.id, current_time)
+                .await;
+        }
         for tx in &justified_node.prepare_txs {
+            if self.aborting.contains_key(&tx.id) {
+                continue;
+            }
+            self.substate_locks
+                .lock(tx, self.shard, self.config.num_shards);
             // local cerb
             if tx.shards.len() == 1 && tx.shards.contains(&self.shard) {
                 self.ready_prepared_mempool.push(SortableByFeeTransaction {
@@ -286,7 +462,13 @@ impl ValidatorNode {
                     fee: tx.effective_fee,
                 });
                 self.subscriber
-                    .on_transaction_prepared_ready(tx.id, self.shard, qc.clone(), current_time)
+                    .on_transaction_prepared_ready(
+                        tx.id,
+                        self.id,
+                        self.shard,
+                        qc.clone(),
+                        current_time,
+                    )
                     .await;
             } else {
                 // remote cerb
@@ -303,12 +485,22 @@ impl ValidatorNode {
                     });
                     self.waiting_prepared_mempool.remove(&tx.id);
                     self.subscriber
-                        .on_transaction_prepared_ready(tx.id, self.shard, qc.clone(), current_time)
+                        .on_transaction_prepared_ready(
+                            tx.id,
+                            self.id,
+                            self.shard,
+                            qc.clone(),
+                            current_time,
+                        )
                         .await;
                 } else {
+                    self.waiting_since
+                        .entry((tx.id, Mempool::WaitingPrepared))
+                        .or_insert((current_time, 0));
                     self.subscriber
                         .on_transaction_prepared_waiting(
                             tx.id,
+                            self.id,
                             self.shard,
                             qc.clone(),
                             current_time,
@@ -328,6 +520,30 @@ impl ValidatorNode {
 
         // ==== precomitted =======
         for tx in &justified_node.precommit_txs {
+            if let Some(reason) = justified_node.abort_reason(tx.id) {
+                if let Entry::Vacant(e) = self.aborting.entry(tx.id) {
+                    e.insert(reason);
+                    self.subscriber
+                        .on_transaction_abort_vote(
+                            tx.id,
+                            self.id,
+                            self.shard,
+                            reason,
+                            justified_node.id,
+                            current_time,
+                        )
+                        .await;
+                }
+            }
+            // An abort does not need to wait for the other shards
+            if self.aborting.contains_key(&tx.id) {
+                self.ready_pre_committed_mempool
+                    .push(SortableByFeeTransaction {
+                        tx: tx.clone(),
+                        fee: tx.effective_fee,
+                    });
+                continue;
+            }
             // local cerb
             if tx.shards.len() == 1 && tx.shards.contains(&self.shard) {
                 self.ready_pre_committed_mempool
@@ -337,16 +553,16 @@ impl ValidatorNode {
                     });
 
                 self.subscriber
-                    .on_transaction_precommit_ready(tx.id, self.shard, qc.clone(), current_time)
+                    .on_transaction_precommit_ready(
+                        tx.id,
+                        self.id,
+                        self.shard,
+                        qc.clone(),
+                        current_time,
+                    )
                     .await;
             } else {
                 // remote cerb
-
-                // self.waiting_pre_committed_mempool
-                //     .push((current_time, tx.clone()));
-                // self.subscriber
-                //     .on_transaction_precommit_waiting(tx.id, self.shard, qc.clone(), current_time)
-                //     .await;
                 let entry = self
                     .waiting_pre_committed_mempool
                     .entry(tx.id)
@@ -361,12 +577,22 @@ impl ValidatorNode {
                         });
                     self.waiting_pre_committed_mempool.remove(&tx.id);
                     self.subscriber
-                        .on_transaction_precommit_ready(tx.id, self.shard, qc.clone(), current_time)
+                        .on_transaction_precommit_ready(
+                            tx.id,
+                            self.id,
+                            self.shard,
+                            qc.clone(),
+                            current_time,
+                        )
                         .await;
                 } else {
+                    self.waiting_since
+                        .entry((tx.id, Mempool::WaitingPreCommitted))
+                        .or_insert((current_time, 0));
                     self.subscriber
                         .on_transaction_precommit_waiting(
                             tx.id,
+                            self.id,
                             self.shard,
                             qc.clone(),
                             current_time,
@@ -389,20 +615,40 @@ impl ValidatorNode {
             }
         }
 
-        // let waiting: Vec<(u128, Arc<Transaction>)> =
-        //     self.waiting_prepared_mempool.drain(..).collect();
-        // for (time, tx) in waiting {
-        //     if !justified_node.precommit_txs.contains(&tx) {
-        //         self.waiting_prepared_mempool.push((time, tx));
-        //     }
-        // }
-
         // ====== Committed ...... Can save to DB ======
 
         for tx in &justified_node.commit_txs {
-            println!("APPLIED TX: {:?}", tx);
+            // The leader decided, this VN may not have heard about the abort yet
+            if let Some(reason) = justified_node.abort_reason(tx.id) {
+                debug!("ABORTED TX: {:?}", tx);
+                self.aborted_transactions.insert(tx.id);
+                self.substate_locks.release(tx.id);
+                self.subscriber
+                    .on_transaction_aborted(
+                        tx.id,
+                        self.id,
+                        self.shard,
+                        reason,
+                        qc.clone(),
+                        current_time,
+                    )
+                    .await;
+                continue;
+            }
+            debug!("APPLIED TX: {:?}", tx);
+            self.committed_transactions.insert(tx.id);
+            self.substate_locks.release(tx.id);
+            self.subscriber
+                .on_transaction_committed(tx.id, self.id, self.shard, qc.clone(), current_time)
+                .await;
             self.subscriber
-                .on_transaction_committed(tx.id, self.shard, qc.clone(), current_time)
+                .pay_fee(
+                    tx,
+                    self.shard,
+                    justified_node.proposed_by,
+                    &qc.votes,
+                    &self.config,
+                )
                 .await;
         }
 
@@ -424,21 +670,168 @@ impl ValidatorNode {
         }
     }
 
-    fn on_commit(&mut self, block: Arc<Block>) {
-        if self.b_exec.height < block.height {
-            let parent = self
-                .blocks
-                .get(&block.parent_id)
-                .expect("justify parent was missing, should request it");
-            self.on_commit(parent.clone());
-            self.execute(&block);
+    /// Records that a shard voted to abort `tx`. The outcome no longer depends on the other shards,
+    /// so the transaction stops waiting for them and goes on to be precommitted and committed as
+    /// aborted. Its locks are held until then. Does nothing if the abort was already known
+    async fn on_abort_vote(
+        &mut self,
+        tx: &Arc<Transaction>,
+        reason: AbortReason,
+        in_block: u32,
+        current_time: u128,
+    ) {
+        if self.aborting.contains_key(&tx.id) || self.has_finished(tx.id) {
+            return;
+        }
+        self.subscriber
+            .on_transaction_abort_vote(tx.id, self.id, self.shard, reason, in_block, current_time)
+            .await;
+        self.aborting.insert(tx.id, reason);
+        self.new_tx_mempool.retain(|t| t.tx.id != tx.id);
+        self.waiting_prepared_mempool.remove(&tx.id);
+        if self.waiting_pre_committed_mempool.remove(&tx.id).is_some() {
+            self.ready_pre_committed_mempool
+                .push(SortableByFeeTransaction {
+                    tx: tx.clone(),
+                    fee: tx.effective_fee,
+                });
+        } else if !self.is_prepared(tx.id) {
+            self.ready_prepared_mempool.push(SortableByFeeTransaction {
+                tx: tx.clone(),
+                fee: tx.effective_fee,
+            });
         }
     }
 
-    fn execute(&mut self, block: &Block) {
-        // for tx in &block.transactions {
-        //     self.subscriber.on_execute(self.id, tx.clone());
-        // }
+    /// Whether this VN has seen every shard prepare the transaction
+    fn is_prepared(&self, tx_id: u32) -> bool {
+        self.has_finished(tx_id)
+            || self.waiting_pre_committed_mempool.contains_key(&tx_id)
+            || self
+                .ready_prepared_mempool
+                .iter()
+                .chain(&self.ready_pre_committed_mempool)
+                .any(|t| t.tx.id == tx_id)
+    }
+
+    /// Finds the transactions that have waited on other shards for longer than their phase's
+    /// timeout. A prepare is retried `timeout_retries` times and then aborted, a precommit is
+    /// always retried. Returns the blocks to send again and the transactions to precommit as
+    /// aborted
+    async fn check_timeouts(
+        &mut self,
+        current_time: u128,
+    ) -> (Vec<(u32, Message)>, Vec<Arc<Transaction>>) {
+        let mut outgoing = vec![];
+        let mut expired = vec![];
+        let mut timers: Vec<_> = self.waiting_since.iter().map(|(k, v)| (*k, *v)).collect();
+        timers.sort();
+        for ((tx_id, mempool), (since, retries)) in timers {
+            let (waiting, timeout) = match mempool {
+                Mempool::WaitingPrepared => {
+                    (&self.waiting_prepared_mempool, self.config.prepare_timeout)
+                }
+                _ => (
+                    &self.waiting_pre_committed_mempool,
+                    self.config.precommit_timeout,
+                ),
+            };
+            let Some((tx, blocks)) = waiting.get(&tx_id).cloned() else {
+                self.waiting_since.remove(&(tx_id, mempool));
+                continue;
+            };
+            let timeout = timeout.as_millis();
+            // Each retry gets a full timeout of its own
+            if timeout == 0 || current_time < since + timeout * (retries as u128 + 1) {
+                continue;
+            }
+            let abort =
+                mempool == Mempool::WaitingPrepared && retries >= self.config.timeout_retries;
+            self.subscriber
+                .on_transaction_timed_out(tx_id, self.id, self.shard, mempool, abort, current_time)
+                .await;
+            if abort {
+                self.waiting_since.remove(&(tx_id, mempool));
+                expired.push(tx);
+                continue;
+            }
+            self.waiting_since
+                .insert((tx_id, mempool), (since, retries + 1));
+            let Some(block) = blocks.get(&self.shard) else {
+                continue;
+            };
+            for shard in tx.shards.iter().filter(|s| !blocks.contains_key(s)) {
+                for node_id in self.committee_manager.get_committee(*shard).await {
+                    outgoing.push((
+                        node_id,
+                        Message::BlockProposal {
+                            id: self.id_provider.next(),
+                            block: block.clone(),
+                        },
+                    ));
+                }
+            }
+        }
+        (outgoing, expired)
+    }
+
+    async fn on_commit(&mut self, block: Arc<Block>, current_time: u128) {
+        // Walk back to the last executed block, then execute in order. Blocks from while the VN
+        // was offline are not executed
+        let mut chain = vec![];
+        let mut next = Some(block);
+        while let Some(block) = next {
+            if self.b_exec.height >= block.height {
+                break;
+            }
+            next = self.blocks.get(&block.parent_id).cloned();
+            chain.push(block);
+        }
+        for block in chain.into_iter().rev() {
+            self.execute(&block, current_time).await;
+            self.subscriber
+                .on_block_committed(self.id, block, current_time)
+                .await;
+        }
+    }
+
+    /// Executes the transactions prepared in `block` that this VN has not executed yet. The VN is
+    /// busy until they are done, so any vote or proposal it makes is held until then.
+    async fn execute(&mut self, block: &Block, current_time: u128) {
+        let mut txs = vec![];
+        for tx in &block.prepare_txs {
+            if self.executed_transactions.insert(tx.id) {
+                txs.push((tx.execution_time, tx.memory));
+                self.subscriber
+                    .on_transaction_executed(self.id, tx.execution_time)
+                    .await;
+            }
+        }
+        if txs.is_empty() {
+            return;
+        }
+        let start = self.busy_until.max(current_time);
+        let outcome = self.executor.execute(start as u64 * 1000, &txs);
+        let busy = (outcome.end.div_ceil(1000) as u128).saturating_sub(start);
+        if busy > 0 {
+            self.busy_until = start + busy;
+            self.subscriber.on_vn_busy(self.id, busy).await;
+        }
+        self.subscriber
+            .on_execution_memory(
+                self.id,
+                outcome.peak_memory,
+                outcome.memory_demand,
+                outcome.memory_stall,
+                outcome.over_budget,
+            )
+            .await;
+    }
+
+    /// The memory this VN used for execution between `from` and `to`. Windows have to be asked
+    /// for in order
+    pub fn memory_usage(&mut self, from: u128, to: u128) -> MemoryUsage {
+        self.executor.usage(from as u64 * 1000, to as u64 * 1000)
     }
 
     fn does_extend(&self, block: &Block, ancestor: &Block) -> bool {
@@ -447,22 +840,43 @@ impl ValidatorNode {
     }
 
     pub async fn update(&mut self, current_time: u128) -> Vec<(u32, Message)> {
-        // todo: messages per second
-        let incoming_messages = self.incoming_messages.drain(..).collect::<Vec<_>>();
+        if current_time < self.busy_until {
+            // Messages wait in the queue until the VN has finished executing
+            return vec![];
+        }
+        let released = std::mem::take(&mut self.held_outgoing);
+        let outgoing = self.handle_messages(current_time).await;
+        if current_time < self.busy_until {
+            self.held_outgoing = outgoing;
+            return released;
+        }
+        released.into_iter().chain(outgoing).collect()
+    }
 
+    async fn handle_messages(&mut self, current_time: u128) -> Vec<(u32, Message)> {
+        // todo: messages per second
         let mut outgoing = vec![];
-        let mut has_new_qc_or_can_propose = false;
-        for (time, message) in incoming_messages {
+        let mut has_new_qc_or_can_propose = std::mem::take(&mut self.propose_next_ms);
+        while let Some((time, message)) = self.incoming_messages.pop_front() {
+            if current_time < self.busy_until {
+                // Started executing, so the rest of the messages have to wait
+                self.incoming_messages.push_front((time, message));
+                self.propose_next_ms |= has_new_qc_or_can_propose;
+                return outgoing;
+            }
+            self.subscriber
+                .on_signatures_checked(self.id, message.signatures())
+                .await;
             match &message {
                 Message::Transaction { tx, .. } => {
                     self.add_transaction(tx.clone(), time);
                 }
                 Message::BlockProposal { block, .. } => {
                     self.time_last_proposal_received = current_time;
-                    if !self.blocks.contains_key(&block.id) {
-                        self.blocks.insert(block.id, block.clone());
+                    if let Entry::Vacant(e) = self.blocks.entry(block.id) {
+                        e.insert(block.clone());
                     } else {
-                        dbg!("Got a duplicate block proposal");
+                        debug!("Got a duplicate block proposal");
                     }
                     outgoing.extend(
                         self.on_receive_proposal(block.clone(), current_time, (time, message))
@@ -480,10 +894,7 @@ impl ValidatorNode {
                         // self.current_height = *height;
                         // self.locked_node = self.high_qc.clone();
                         // self.on_commit(self.high_qc.clone());
-                        self.new_view_votes
-                            .entry(*height)
-                            .or_insert_with(Vec::new)
-                            .push(*from);
+                        self.new_view_votes.entry(*height).or_default().push(*from);
                         let n = self.committee_manager.get_committee(self.shard).await.len();
                         if self.new_view_votes.get(height).unwrap().len() > n - ((n - 1) / 3) {
                             // must propose.
@@ -497,19 +908,18 @@ impl ValidatorNode {
                     vote_by,
                     ..
                 } => {
-                    dbg!("Got a vote");
-                    dbg!(self.id);
+                    debug!("VN {} got a vote", self.id);
                     has_new_qc_or_can_propose |= self
                         .on_receive_vote(*block_id, *block_height, *vote_by, current_time)
                         .await;
-                    dbg!(has_new_qc_or_can_propose);
+                    debug!("has_new_qc_or_can_propose: {}", has_new_qc_or_can_propose);
                 }
                 Message::RequestBlock {
                     block_id,
                     request_by,
                     ..
                 } => {
-                    if let Some(block) = self.blocks.get(&block_id) {
+                    if let Some(block) = self.blocks.get(block_id) {
                         outgoing.push((
                             *request_by,
                             Message::RequestBlockResponse {
@@ -520,12 +930,12 @@ impl ValidatorNode {
                     }
                 }
                 Message::RequestBlockResponse { block, .. } => {
-                    if !self.blocks.contains_key(&block.id) {
-                        dbg!("Got a block response");
-                        self.blocks.insert(block.id, block.clone());
+                    if let Entry::Vacant(e) = self.blocks.entry(block.id) {
+                        debug!("Got a block response");
+                        e.insert(block.clone());
                         // unsnooze messages
                         let messages = self.snoozed_messages.remove(&block.id).unwrap_or_default();
-                        for (time, message) in messages {
+                        for (_time, message) in messages {
                             // self.incoming_messages.push_back((time, message));
                             outgoing.push((self.id, message));
                         }
@@ -538,13 +948,19 @@ impl ValidatorNode {
         if has_new_qc_or_can_propose
         // ||
         {
-            dbg!("Has new qc, checking if I am the leader");
-            self.print_stats();
+            debug!("Has new qc, checking if I am the leader");
+            debug!("{}", self.stats());
             if self.is_leader().await
             // && (self.last_proposed_round.is_none()
             //     || self.last_proposed_round.unwrap() < self.hotstuff_round)
             {
-                outgoing.extend(self.on_propose(current_time).await);
+                if self.last_proposed_at == Some(current_time) {
+                    // A committee of one forms a QC as soon as it proposes, which would otherwise
+                    // never let time move on
+                    self.propose_next_ms = true;
+                } else {
+                    outgoing.extend(self.on_propose(current_time).await);
+                }
             }
         } else {
             // propose early to avoid failure
@@ -570,6 +986,22 @@ impl ValidatorNode {
         outgoing
     }
 
+    /// The next time `update` has to be called even if no messages arrive, so that the VN can
+    /// propose early or time out the leader
+    pub fn next_timeout(&self, current_time: u128) -> Option<u128> {
+        if self.busy_until > current_time {
+            return Some(self.busy_until);
+        }
+        if self.propose_next_ms {
+            return Some(current_time + 1);
+        }
+        let delta = self.config.delta.as_millis();
+        [delta / 2, delta]
+            .into_iter()
+            .map(|d| self.time_last_proposal_received + d)
+            .find(|t| *t > current_time)
+    }
+
     async fn on_next_sync_view(&mut self) -> Vec<(u32, Message)> {
         // Exclude the first set up
         if self.current_height != 0 {
@@ -600,10 +1032,10 @@ impl ValidatorNode {
         current_time: u128,
     ) -> bool {
         if block_height < self.current_height {
-            eprintln!("Received a vote for a block that is too old");
+            debug!("Received a vote for a block that is too old");
             return false;
         }
-        let votes = self.votes.entry(block_id).or_insert_with(Vec::new);
+        let votes = self.votes.entry(block_id).or_default();
         if !votes.contains(&vote_by) {
             votes.push(vote_by);
             self.subscriber
@@ -623,7 +1055,7 @@ impl ValidatorNode {
                 votes.clone(),
             ));
             self.subscriber
-                .on_qc_created(qc.id, current_time, qc.block_id)
+                .on_qc_created(qc.id, self.id, current_time, qc.block_id)
                 .await;
             // Apply the node so that we can propose a new block using the updated mempools
             let qc_block = self
@@ -641,11 +1073,13 @@ impl ValidatorNode {
 
     fn update_high_qc(&mut self, qc: Arc<Qc>) {
         if qc.block_height > self.high_qc.block_height {
-            self.b_leaf = self
-                .blocks
-                .get(&qc.block_id)
-                .expect("justify parent was missing, should request it")
-                .clone();
+            // A VN that has been offline may not have the block yet, it will request it when it
+            // gets the next proposal
+            let Some(block) = self.blocks.get(&qc.block_id) else {
+                debug!("VN {} does not have the block for the high qc", self.id);
+                return;
+            };
+            self.b_leaf = block.clone();
             self.high_qc = qc;
         }
     }
@@ -658,57 +1092,111 @@ impl ValidatorNode {
         next_leader == self.id
     }
 
+    /// Whether the next transaction in `pool` can go into a block that already takes `taken`
+    /// transactions from it and `block_bytes` bytes of transactions in all. The first transaction
+    /// always fits, so that one larger than `max_block_size` cannot stall the shard
+    fn next_fits(
+        &self,
+        pool: &BinaryHeap<SortableByFeeTransaction>,
+        taken: usize,
+        block_bytes: u64,
+    ) -> bool {
+        pool.peek().is_some_and(|next| {
+            taken < self.config.max_tx_per_step_per_block
+                && (block_bytes == 0 || block_bytes + next.tx.size() <= self.config.max_block_size)
+        })
+    }
+
     async fn create_leaf(
         &mut self,
         parent_id: u32,
         qc: Arc<Qc>,
         height: u32,
+        timed_out: Vec<Arc<Transaction>>,
         current_time: u128,
     ) -> Arc<Block> {
         let mut prepare_txs = vec![];
-        let mut precommit_txs = vec![];
+        let mut abort_txs = vec![];
+        // This shard has already voted to prepare a transaction that timed out, so it precommits
+        // it as aborted instead
+        let mut aborted: Vec<_> = timed_out
+            .iter()
+            .map(|tx| (tx.id, AbortReason::Timeout))
+            .collect();
+        let mut precommit_txs = timed_out;
         let mut commit_txs = vec![];
+        // Inputs locked by the transactions already picked for this block
+        let mut block_locks = SubstateLocks::default();
+        // Bytes of the transactions already picked for this block
+        let mut block_bytes: u64 = precommit_txs.iter().map(|tx| tx.size()).sum();
         // The transactions in the QC have not yet been moved to their new pools, so we need to make sure we don't
         // add them again here.
 
-        let qc_block = self.blocks.get(&qc.block_id).unwrap();
-
-        self.print_stats();
+        debug!("{}", self.stats());
         loop {
-            if prepare_txs.len() < self.config.max_tx_per_step_per_block {
+            let take_new = self.next_fits(&self.new_tx_mempool, prepare_txs.len(), block_bytes);
+            if take_new {
                 if let Some(transaction) = self.new_tx_mempool.pop() {
-                    // if !qc_block.prepare_txs.contains(&transaction) {
-                    prepare_txs.push(transaction.tx);
-                    // }
+                    let tx = transaction.tx;
+                    block_bytes += tx.size();
+                    let num_shards = self.config.num_shards;
+                    let conflict = self
+                        .substate_locks
+                        .conflict(&tx, self.shard, num_shards)
+                        .or_else(|| block_locks.conflict(&tx, self.shard, num_shards));
+                    if tx.fails {
+                        abort_txs.push((tx, AbortReason::ExecutionFailure));
+                    } else if let Some((address, held_by)) = conflict {
+                        self.subscriber
+                            .on_lock_conflict(
+                                tx.id,
+                                self.id,
+                                self.shard,
+                                address,
+                                held_by,
+                                current_time,
+                            )
+                            .await;
+                        abort_txs.push((tx, AbortReason::LockConflict));
+                    } else {
+                        block_locks.lock(&tx, self.shard, num_shards);
+                        prepare_txs.push(tx);
+                    }
                 }
             }
-            if precommit_txs.len() < self.config.max_tx_per_step_per_block {
+            let take_prepared = self.next_fits(
+                &self.ready_prepared_mempool,
+                precommit_txs.len(),
+                block_bytes,
+            );
+            if take_prepared {
                 if let Some(tx) = self.ready_prepared_mempool.pop() {
-                    // if !qc_block.precommit_txs.contains(&tx) {
+                    block_bytes += tx.tx.size();
+                    if let Some(reason) = self.aborting.get(&tx.tx.id) {
+                        aborted.push((tx.tx.id, *reason));
+                    }
                     precommit_txs.push(tx.tx);
-                    // }
                 }
             }
-            if commit_txs.len() < self.config.max_tx_per_step_per_block {
+            let take_pre_committed = self.next_fits(
+                &self.ready_pre_committed_mempool,
+                commit_txs.len(),
+                block_bytes,
+            );
+            if take_pre_committed {
                 if let Some(tx) = self.ready_pre_committed_mempool.pop() {
-                    // if !qc_block.commit_txs.contains(&tx) {
+                    block_bytes += tx.tx.size();
+                    if let Some(reason) = self.aborting.get(&tx.tx.id) {
+                        aborted.push((tx.tx.id, *reason));
+                    }
                     commit_txs.push(tx.tx);
-                    // }
                 }
             }
 
-            if (prepare_txs.len() == self.config.max_tx_per_step_per_block
-                || self.new_tx_mempool.is_empty())
-                && (precommit_txs.len() == self.config.max_tx_per_step_per_block
-                    || self.ready_prepared_mempool.is_empty())
-                && (commit_txs.len() == self.config.max_tx_per_step_per_block
-                    || self.ready_pre_committed_mempool.is_empty())
-            {
+            if !take_new && !take_prepared && !take_pre_committed {
                 break;
             }
         }
-        // let prepare_txs = self.new_tx_mempool.pop()
-        // let precommit_txs = self.ready_prepare_mempool.drain(..self.config.max_tx_per_step_per_block).collect();
         let block = Arc::new(Block::new(
             self.id_provider.next(),
             parent_id,
@@ -719,6 +1207,8 @@ impl ValidatorNode {
             prepare_txs,
             precommit_txs,
             commit_txs,
+            abort_txs,
+            aborted,
         ));
         self.subscriber
             .on_create_leaf(block.clone(), current_time)
@@ -730,17 +1220,20 @@ impl ValidatorNode {
         let high_qc = self.high_qc.clone();
         // if high_qc.view number > generic_qc then self.generic_qc = high_qc
 
-        let mut outgoing = vec![];
+        let (mut outgoing, timed_out) = self.check_timeouts(current_time).await;
 
         let block = self
             .create_leaf(
                 self.b_leaf.id,
                 high_qc,
                 self.b_leaf.height + 1,
+                timed_out,
                 current_time,
             )
             .await;
         self.last_proposed_round = Some(self.current_height);
+        self.last_proposed_at = Some(current_time);
+        self.execute(&block, current_time).await;
 
         let involved_shards = block.involved_shards();
         // send to all nodes.
@@ -776,3 +1269,69 @@ impl ValidatorNode {
         outgoing
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::SimulationConfig;
+    use crate::simulation::SimulationBuilder;
+    use crate::transaction::AbortReason;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn failed_transactions_abort_everywhere_and_release_their_locks() {
+        let config = SimulationConfig {
+            seed: Some(12),
+            num_vns: 12,
+            num_shards: 3,
+            num_transactions: 40,
+            tx_failure_percent: 30,
+            probability_2_shards: 50,
+            probability_3_shards: 30,
+            until_quiescent: true,
+            max_time: Some(Duration::from_secs(60).into()),
+            ..Default::default()
+        };
+        let result = SimulationBuilder::from_config(config).build().run().await;
+        assert!(result.quiescent);
+
+        let failed: Vec<_> = result
+            .metrics
+            .transactions
+            .values()
+            .filter(|t| t.tx.fails)
+            .collect();
+        assert!(failed.iter().any(|t| t.tx.shards.len() > 1));
+        let aborted = result.metrics.aborted_transactions();
+        assert_eq!(aborted.len(), failed.len());
+        for record in failed {
+            let id = record.tx.id;
+            assert_eq!(record.abort_reason, Some(AbortReason::ExecutionFailure));
+            assert!(aborted.iter().any(|t| t.tx.id == id));
+            assert!(result
+                .metrics
+                .committed_transactions()
+                .iter()
+                .all(|t| t.tx.id != id));
+            for vn in result
+                .vns
+                .values()
+                .filter(|vn| record.tx.shards.contains(&vn.shard))
+            {
+                assert!(
+                    vn.aborted_transactions.contains(&id),
+                    "VN {} tx {}",
+                    vn.id,
+                    id
+                );
+                assert!(!vn.committed_transactions.contains(&id));
+            }
+        }
+        assert_eq!(
+            result.metrics.committed_transactions().len() + aborted.len(),
+            40
+        );
+        for vn in result.vns.values() {
+            assert!(vn.substate_locks.is_empty(), "VN {} holds locks", vn.id);
+        }
+    }
+}
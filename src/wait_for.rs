@@ -0,0 +1,400 @@
+use crate::simulation::SimulationResult;
+use crate::transaction::{Shard, Transaction};
+use crate::validator_node::{Mempool, ValidatorNode, WaitingMempool};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// How far the VNs of a shard have got with a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Progress {
+    /// No VN in the shard has the transaction in a mempool
+    NotQueued,
+    /// The mempool of the VN that is furthest along. The waiting mempools only count once the
+    /// shard has included the transaction in a block itself
+    In(Mempool),
+    /// Some VN in the shard has committed or aborted it
+    Finished,
+}
+
+impl Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Progress::NotQueued => write!(f, "not in any mempool"),
+            Progress::In(mempool) => write!(f, "{}", mempool),
+            Progress::Finished => write!(f, "finished"),
+        }
+    }
+}
+
+/// A block that included a transaction in the phase a shard is waiting in
+#[derive(Debug, Clone, Copy)]
+pub struct BlockRef {
+    pub shard: Shard,
+    pub id: u32,
+    pub height: u32,
+}
+
+/// A shard that has included a transaction in a block and is waiting for another shard's block
+#[derive(Debug, Clone)]
+pub struct Wait {
+    pub tx_id: u32,
+    pub shard: Shard,
+    pub on: Shard,
+    /// The mempool `shard` is waiting in
+    pub mempool: Mempool,
+    /// How far `on` has got with the transaction
+    pub on_progress: Progress,
+    /// When `shard` started waiting
+    pub since: u128,
+    /// The blocks `shard` has seen include the transaction in this phase, its own first. The
+    /// shards missing from them are the ones it waits on
+    pub blocks: Vec<BlockRef>,
+}
+
+/// Which shards are waiting on which for their pending cross-shard transactions, at one point in
+/// the run
+#[derive(Debug, Clone)]
+pub struct WaitForGraph {
+    pub t: u128,
+    pub starvation_threshold: u128,
+    /// Sorted by transaction, then waiting shard, then the shard it waits on
+    pub waits: Vec<Wait>,
+}
+
+impl WaitForGraph {
+    /// Builds the graph from the mempools of every VN. A shard is taken to be as far along as its
+    /// furthest VN, so a VN that is behind the rest of its shard does not add waits
+    pub fn build(vns: &BTreeMap<u32, ValidatorNode>, t: u128, starvation_threshold: u128) -> Self {
+        let contents: BTreeMap<u32, HashMap<u32, Vec<Mempool>>> = vns
+            .iter()
+            .map(|(id, vn)| (*id, vn.mempool_contents()))
+            .collect();
+
+        // Only a transaction in a waiting mempool can be waiting on another shard
+        let mut pending: BTreeMap<u32, Arc<Transaction>> = BTreeMap::new();
+        for vn in vns.values() {
+            for (tx, _) in vn
+                .waiting_prepared_mempool
+                .values()
+                .chain(vn.waiting_pre_committed_mempool.values())
+            {
+                // Foreign blocks also carry transactions that do not touch this VN's shard
+                if tx.shards.contains(&vn.shard) {
+                    pending.entry(tx.id).or_insert_with(|| tx.clone());
+                }
+            }
+        }
+
+        let mut waits = vec![];
+        for tx in pending.values() {
+            let furthest: BTreeMap<Shard, (Progress, &ValidatorNode)> = tx
+                .shards
+                .iter()
+                .filter_map(|shard| {
+                    vns.values()
+                        .filter(|vn| vn.shard == *shard)
+                        .map(|vn| (progress(vn, &contents[&vn.id], tx), vn))
+                        .max_by(|(a, vn_a), (b, vn_b)| {
+                            a.cmp(b)
+                                .then(blocks_seen(vn_a, tx.id).cmp(&blocks_seen(vn_b, tx.id)))
+                                .then(vn_b.id.cmp(&vn_a.id))
+                        })
+                        .map(|furthest| (*shard, furthest))
+                })
+                .collect();
+            for (shard, (progress, vn)) in &furthest {
+                let Progress::In(mempool) = progress else {
+                    continue;
+                };
+                let waiting = match mempool {
+                    Mempool::WaitingPrepared => &vn.waiting_prepared_mempool,
+                    Mempool::WaitingPreCommitted => &vn.waiting_pre_committed_mempool,
+                    _ => continue,
+                };
+                let Some((_, blocks)) = waiting.get(&tx.id) else {
+                    continue;
+                };
+                let mut block_refs: Vec<_> = blocks
+                    .values()
+                    .map(|b| BlockRef {
+                        shard: b.shard,
+                        id: b.id,
+                        height: b.height,
+                    })
+                    .collect();
+                block_refs.sort_by_key(|b| (b.shard != *shard, b.shard));
+                for on in tx.shards.iter().filter(|s| !blocks.contains_key(s)) {
+                    waits.push(Wait {
+                        tx_id: tx.id,
+                        shard: *shard,
+                        on: *on,
+                        mempool: *mempool,
+                        on_progress: furthest
+                            .get(on)
+                            .map(|(p, _)| *p)
+                            .unwrap_or(Progress::NotQueued),
+                        since: vn.waiting_since(tx.id, *mempool).unwrap_or(t),
+                        blocks: block_refs.clone(),
+                    });
+                }
+            }
+        }
+
+        Self {
+            t,
+            starvation_threshold,
+            waits,
+        }
+    }
+
+    /// The waits that have gone on for at least the starvation threshold
+    pub fn starved(&self) -> Vec<&Wait> {
+        self.waits
+            .iter()
+            .filter(|w| self.t - w.since >= self.starvation_threshold)
+            .collect()
+    }
+
+    /// Groups of shards that all wait on each other through starved transactions, so none of
+    /// them can move on until a timeout aborts one of the transactions
+    pub fn deadlocks(&self) -> Vec<Vec<Shard>> {
+        let mut edges: BTreeMap<Shard, BTreeSet<Shard>> = BTreeMap::new();
+        for wait in self.starved() {
+            edges.entry(wait.shard).or_default().insert(wait.on);
+        }
+        let reachable = |from: Shard| {
+            let mut seen = BTreeSet::new();
+            let mut next = vec![from];
+            while let Some(shard) = next.pop() {
+                for to in edges.get(&shard).into_iter().flatten() {
+                    if seen.insert(*to) {
+                        next.push(*to);
+                    }
+                }
+            }
+            seen
+        };
+        let reach: BTreeMap<Shard, BTreeSet<Shard>> =
+            edges.keys().map(|s| (*s, reachable(*s))).collect();
+
+        let mut found = BTreeSet::new();
+        let mut deadlocks = vec![];
+        for (shard, reaches) in &reach {
+            if found.contains(shard) || !reaches.contains(shard) {
+                continue;
+            }
+            let cycle: Vec<Shard> = reaches
+                .iter()
+                .filter(|other| reach.get(other).is_some_and(|r| r.contains(shard)))
+                .cloned()
+                .collect();
+            found.extend(cycle.iter().cloned());
+            deadlocks.push(cycle);
+        }
+        deadlocks
+    }
+}
+
+/// How far `vn` has got with `tx`, given what is in its mempools
+fn progress(
+    vn: &ValidatorNode,
+    contents: &HashMap<u32, Vec<Mempool>>,
+    tx: &Transaction,
+) -> Progress {
+    if vn.has_finished(tx.id) {
+        return Progress::Finished;
+    }
+    let included = |waiting: &WaitingMempool| {
+        waiting
+            .get(&tx.id)
+            .is_some_and(|(_, blocks)| blocks.contains_key(&vn.shard))
+    };
+    contents
+        .get(&tx.id)
+        .into_iter()
+        .flatten()
+        .filter(|mempool| match mempool {
+            Mempool::WaitingPrepared => included(&vn.waiting_prepared_mempool),
+            Mempool::WaitingPreCommitted => included(&vn.waiting_pre_committed_mempool),
+            _ => true,
+        })
+        .max()
+        .map(|mempool| Progress::In(*mempool))
+        .unwrap_or(Progress::NotQueued)
+}
+
+fn blocks_seen(vn: &ValidatorNode, tx_id: u32) -> usize {
+    [
+        &vn.waiting_prepared_mempool,
+        &vn.waiting_pre_committed_mempool,
+    ]
+    .iter()
+    .filter_map(|waiting| waiting.get(&tx_id))
+    .map(|(_, blocks)| blocks.len())
+    .sum()
+}
+
+/// The wait-for graphs built during the run, and at its end
+#[derive(Debug, Clone)]
+pub struct WaitForReport {
+    pub graphs: Vec<WaitForGraph>,
+}
+
+impl WaitForReport {
+    /// How many starved transactions are listed for the end of the run
+    const MAX_STARVED: usize = 10;
+
+    pub fn from_result(result: &SimulationResult) -> Self {
+        Self {
+            graphs: result.wait_for.clone(),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("==== Wait-for graph ====");
+        let Some(last) = self.graphs.last() else {
+            println!("The wait-for graph was not built");
+            return;
+        };
+        for graph in &self.graphs[..self.graphs.len() - 1] {
+            let starved = graph.starved();
+            if starved.is_empty() {
+                continue;
+            }
+            println!(
+                "At {}ms: {} waits, {} starved, deadlocked shards: {}",
+                graph.t,
+                graph.waits.len(),
+                starved.len(),
+                format_deadlocks(&graph.deadlocks())
+            );
+        }
+
+        let starved = last.starved();
+        println!(
+            "At the end ({}ms): {} waits between shards, {} waited {}ms or more",
+            last.t,
+            last.waits.len(),
+            starved.len(),
+            last.starvation_threshold
+        );
+        for deadlock in last.deadlocks() {
+            println!("Deadlock between shards {}:", format_shards(&deadlock));
+            for wait in starved
+                .iter()
+                .filter(|w| deadlock.contains(&w.shard) && deadlock.contains(&w.on))
+            {
+                print_wait(wait, last.t);
+            }
+        }
+        if !starved.is_empty() {
+            println!("Starved transactions:");
+            for wait in starved.iter().take(Self::MAX_STARVED) {
+                print_wait(wait, last.t);
+            }
+            if starved.len() > Self::MAX_STARVED {
+                println!("  ... and {} more", starved.len() - Self::MAX_STARVED);
+            }
+        }
+    }
+}
+
+fn print_wait(wait: &Wait, t: u128) {
+    println!(
+        "  Tx {}: shard {} has waited {}ms in {} on shard {}, which is {}",
+        wait.tx_id,
+        wait.shard.0,
+        t - wait.since,
+        wait.mempool,
+        wait.on.0,
+        wait.on_progress
+    );
+    println!(
+        "    included in: {}",
+        wait.blocks
+            .iter()
+            .map(|b| format!("shard {} block {} at height {}", b.shard.0, b.id, b.height))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+fn format_shards(shards: &[Shard]) -> String {
+    shards
+        .iter()
+        .map(|s| s.0.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn format_deadlocks(deadlocks: &[Vec<Shard>]) -> String {
+    if deadlocks.is_empty() {
+        return "none".to_string();
+    }
+    deadlocks
+        .iter()
+        .map(|d| format!("[{}]", format_shards(d)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A graph at 10s where each edge is a transaction that `shard` has waited on `on` for since
+    /// `since`, and waits of 2s or more are starved
+    fn graph(edges: &[(u32, u32, u128)]) -> WaitForGraph {
+        WaitForGraph {
+            t: 10_000,
+            starvation_threshold: 2_000,
+            waits: edges
+                .iter()
+                .enumerate()
+                .map(|(i, (shard, on, since))| Wait {
+                    tx_id: i as u32,
+                    shard: Shard(*shard),
+                    on: Shard(*on),
+                    mempool: Mempool::WaitingPrepared,
+                    on_progress: Progress::In(Mempool::WaitingPrepared),
+                    since: *since,
+                    blocks: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    fn shards(ids: &[u32]) -> Vec<Shard> {
+        ids.iter().map(|id| Shard(*id)).collect()
+    }
+
+    #[test]
+    fn two_shards_waiting_on_each_other_deadlock() {
+        let graph = graph(&[(0, 1, 0), (1, 0, 1_000)]);
+        assert_eq!(graph.starved().len(), 2);
+        assert_eq!(graph.deadlocks(), vec![shards(&[0, 1])]);
+    }
+
+    #[test]
+    fn a_three_shard_cycle_is_one_deadlock() {
+        // Shard 3 waits on the cycle without being part of it
+        let graph = graph(&[(0, 1, 0), (1, 2, 0), (2, 0, 0), (3, 0, 0)]);
+        assert_eq!(graph.deadlocks(), vec![shards(&[0, 1, 2])]);
+    }
+
+    #[test]
+    fn a_chain_of_waits_is_not_a_deadlock() {
+        let graph = graph(&[(0, 1, 0), (1, 2, 0), (0, 2, 0)]);
+        assert_eq!(graph.starved().len(), 3);
+        assert!(graph.deadlocks().is_empty());
+    }
+
+    #[test]
+    fn only_starved_waits_count() {
+        // Shard 1 only started waiting a second ago
+        let graph = graph(&[(0, 1, 0), (1, 0, 9_000)]);
+        assert_eq!(graph.starved().len(), 1);
+        assert!(graph.deadlocks().is_empty());
+    }
+}